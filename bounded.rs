@@ -0,0 +1,279 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref,DerefMut};
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+
+use super::codec::{Bytes, Codec, Reader, items_exact_len, try_items_len};
+use super::error::{DecodeError, EncodeError};
+use super::owned::IntoOwned;
+use super::prefix::{LengthPrefix, LengthPrefixed, encode_prefixed, try_encode_prefixed};
+#[cfg(feature = "bytes")]
+use super::prefix::put_prefix;
+
+///Check a decoded length against the declared `min..=max`
+fn check_decoded(offset: usize, len: usize, min: usize, max: usize) -> Result<(),DecodeError> {
+  if len < min || len > max {
+    return Err(DecodeError::LengthOutOfRange { offset, len, min, max });
+  }
+  Ok(())
+}
+
+///`max` as the `MAX` of a `Bounded` prefix, failing to compile
+///when it does not fit in the prefix underneath
+const fn bounded_max(max: usize, prefix_max: u64) -> u64 {
+  assert!(max as u64 <= prefix_max, "MAX does not fit in the length prefix");
+  max as u64
+}
+
+///The `L` length prefix of a value declared with `<MIN..MAX>`
+///bounds on its length in bytes. Reading a length outside them
+///fails, so does `try_encode`, and debug builds check `encode`.
+///A `MAX` larger than `L` can express does not compile.
+///
+///```compile_fail
+///use codecbase::{BoundedPayload, Codec, U8};
+///BoundedPayload::<U8, 0, 1000>::from_slice(&[0; 300]).get_encoding();
+///```
+pub struct Bounded<L, const MIN: usize, const MAX: usize>(PhantomData<fn() -> L>);
+
+impl<L: LengthPrefix, const MIN: usize, const MAX: usize> LengthPrefix for Bounded<L, MIN, MAX> {
+  const MAX: u64 = bounded_max(MAX, L::MAX);
+  const WIDTH: Option<usize> = L::WIDTH;
+  const DEBUG_NAME: &'static str = "BoundedPayload";
+
+  fn read_len(r: &mut Reader) -> Result<usize,DecodeError> {
+    let offset = r.absolute_offset();
+    let len = L::read_len(r)?;
+    check_decoded(offset, len, MIN, Self::MAX as usize)?;
+    Ok(len)
+  }
+
+  fn prefix_len(len: usize) -> usize {
+    L::prefix_len(len)
+  }
+
+  fn put_len(len: usize, slot: &mut [u8]) {
+    L::put_len(len, slot)
+  }
+
+  fn check_body_len(len: usize) -> Result<(),EncodeError> {
+    if len < MIN || len as u64 > Self::MAX {
+      return Err(EncodeError::LengthOutOfRange { min: MIN, max: MAX, actual: len });
+    }
+    Ok(())
+  }
+}
+
+///A vector declared in the TLS presentation language as
+///`T items<MIN..MAX>`, with an `L` length prefix. The bounds are
+///on the length in bytes, and are enforced by `try_encode` and
+///`read`. When every `T` encodes to the same length the vector
+///must also hold a whole number of them.
+///
+///```ignore
+/////CipherSuite cipher_suites<2..2^16-2>;
+///type CipherSuites = BoundedVec<U16, CipherSuite, 2, 0xfffe>;
+///```
+pub struct BoundedVec<L, T, const MIN: usize, const MAX: usize>(pub Vec<T>, PhantomData<fn() -> L>);
+
+impl<L, T, const MIN: usize, const MAX: usize> BoundedVec<L, T, MIN, MAX> {
+
+  ///Wrap `items`, the bounds are checked when encoding
+  pub fn new(items: Vec<T>) -> Self {
+    BoundedVec(items, PhantomData)
+  }
+
+  pub fn into_inner(self) -> Vec<T> {
+    self.0
+  }
+}
+
+impl<L, T, const MIN: usize, const MAX: usize> Deref for BoundedVec<L, T, MIN, MAX> {
+  type Target = Vec<T>;
+  fn deref(&self) -> &Vec<T> {
+    &self.0
+  }
+}
+
+impl<L, T, const MIN: usize, const MAX: usize> DerefMut for BoundedVec<L, T, MIN, MAX> {
+  fn deref_mut(&mut self) -> &mut Vec<T> {
+    &mut self.0
+  }
+}
+
+impl<L, T, const MIN: usize, const MAX: usize> From<Vec<T>> for BoundedVec<L, T, MIN, MAX> {
+  fn from(x: Vec<T>) -> Self {
+    BoundedVec::new(x)
+  }
+}
+
+impl<L, T: Clone, const MIN: usize, const MAX: usize> Clone for BoundedVec<L, T, MIN, MAX> {
+  fn clone(&self) -> Self {
+    BoundedVec::new(self.0.clone())
+  }
+}
+
+impl<L, T: PartialEq, const MIN: usize, const MAX: usize> PartialEq for BoundedVec<L, T, MIN, MAX> {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+impl<L, T: Eq, const MIN: usize, const MAX: usize> Eq for BoundedVec<L, T, MIN, MAX> { }
+
+impl<L, T: fmt::Debug, const MIN: usize, const MAX: usize> fmt::Debug for BoundedVec<L, T, MIN, MAX> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_tuple("BoundedVec").field(&self.0).finish()
+  }
+}
+
+impl<'a, L: LengthPrefix, T: Codec<'a>, const MIN: usize, const MAX: usize> Codec<'a> for BoundedVec<L, T, MIN, MAX> {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    encode_prefixed::<Bounded<L, MIN, MAX>, _, _>(bytes, || items_exact_len(&self.0), |bytes| {
+      for i in self.0.iter() {
+        i.encode(bytes);
+      }
+    });
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    try_encode_prefixed::<Bounded<L, MIN, MAX>, _, _>(bytes, || items_exact_len(&self.0),
+      |bytes| self.0.iter().try_for_each(|i| i.try_encode(bytes)))
+  }
+  fn exact_len(&self) -> Option<usize> {
+    items_exact_len(&self.0).map(|len| L::prefix_len(len) + len)
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    let len = try_items_len(&self.0)?;
+    Bounded::<L, MIN, MAX>::check_body_len(len)?;
+    Ok(L::prefix_len(len) + len)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    match items_exact_len(&self.0) {
+      Some(len) => {
+        put_prefix::<L, _>(len, buf);
+        for i in self.0.iter() {
+          i.encode_buf(buf);
+        }
+      }
+      //finding the length means encoding them, so keep that encoding
+      None => buf.put_slice(&self.get_encoding())
+    }
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let offset = r.absolute_offset();
+    let len = Bounded::<L, MIN, MAX>::read_len(r)?;
+    //the length must hold a whole number of fixed length items,
+    //and only an empty vector can hold ones of no length
+    match T::FIXED_LEN {
+      Some(size) if len.checked_rem(size).unwrap_or(len) != 0 =>
+        return Err(DecodeError::LengthNotMultiple { offset, len, size }),
+      _ => ()
+    }
+    let mut sub = r.sub(len)?;
+    let mut ret: Vec<T> = Vec::new();
+    while sub.any_left() {
+      let item = sub.context_index(ret.len(), Reader::read_element)?;
+      sub.push_limited(&mut ret, item)?;
+    }
+    r.end_sub(sub)?;
+    Ok(BoundedVec::new(ret))
+  }
+}
+
+impl<L: 'static, T: IntoOwned, const MIN: usize, const MAX: usize> IntoOwned for BoundedVec<L, T, MIN, MAX> {
+  type Owned = BoundedVec<L, T::Owned, MIN, MAX>;
+  fn into_owned(self) -> Self::Owned {
+    BoundedVec::new(self.0.into_owned())
+  }
+}
+
+///Opaque bytes declared as `opaque data<MIN..MAX>`, with an `L`
+///length prefix. Like the payload types, but `try_encode` and
+///`read` enforce the bounds.
+///
+///```ignore
+/////opaque legacy_session_id<0..32>;
+///type SessionId<'a> = BoundedPayload<'a, U8, 0, 32>;
+///```
+pub type BoundedPayload<'a, L, const MIN: usize, const MAX: usize> = LengthPrefixed<Bounded<L, MIN, MAX>, Bytes<'a>>;
+
+#[test]
+fn test_bounded() {
+  use super::prefix::{U8, U16, Varint};
+
+  //opaque legacy_session_id<0..32>
+  type SessionId<'a> = BoundedPayload<'a, U8, 0, 32>;
+  //CipherSuite cipher_suites<2..2^16-2>
+  type CipherSuites = BoundedVec<U16, u16, 2, 0xfffe>;
+
+  let id = SessionId::from_slice(&[7; 32]);
+  let enc = id.try_get_encoding().unwrap();
+  assert_eq!(enc.len(), 33);
+  assert_eq!(SessionId::read_exact(&enc), Ok(id));
+  assert_eq!(SessionId::new(vec![0; 33]).try_get_encoding(),
+    Err(EncodeError::LengthOutOfRange { min: 0, max: 32, actual: 33 }));
+  let mut long = vec![33];
+  long.extend_from_slice(&[0; 33]);
+  assert_eq!(SessionId::read_exact(&long),
+    Err(DecodeError::LengthOutOfRange { offset: 0, len: 33, min: 0, max: 32 }));
+
+  let suites = CipherSuites::new(vec![0x1301, 0x1302]);
+  assert_eq!(suites.try_get_encoding(), Ok(vec![0,4, 0x13,0x01, 0x13,0x02]));
+  assert_eq!(CipherSuites::read_exact(&[0,4, 0x13,0x01, 0x13,0x02]), Ok(suites));
+
+  //an empty list is below the minimum, and nothing is written
+  let mut bytes = vec![0xaa];
+  assert_eq!(CipherSuites::new(vec![]).try_encode(&mut bytes),
+    Err(EncodeError::LengthOutOfRange { min: 2, max: 0xfffe, actual: 0 }));
+  assert_eq!(bytes, vec![0xaa]);
+  assert_eq!(CipherSuites::read(&mut Reader::init(&[0,0])),
+    Err(DecodeError::LengthOutOfRange { offset: 0, len: 0, min: 2, max: 0xfffe }));
+
+  //an odd length cannot hold whole cipher suites
+  let mut r = Reader::init(&[0xff, 0,3, 0x13,0x01, 0x13]);
+  r.read_u8().unwrap();
+  assert_eq!(CipherSuites::read(&mut r),
+    Err(DecodeError::LengthNotMultiple { offset: 1, len: 3, size: 2 }));
+
+  //the multiple is worked out for arrays and tuples as well
+  assert_eq!(<(u8, [u16; 3])>::FIXED_LEN, Some(7));
+  assert_eq!(<(u8, BoundedVec<U8, u8, 0, 1>)>::FIXED_LEN, None);
+
+  //a varint prefix is written in front of the items in place
+  type Varints = BoundedVec<Varint, u16, 0, 0x3fff>;
+  let v = Varints::new(vec![0x0102; 100]);
+  let enc = v.try_get_encoding().unwrap();
+  assert_eq!(&enc[.. 4], &[0xc8, 0x01, 0x01, 0x02]);
+  assert_eq!(enc.len(), 202);
+  assert_eq!(Varints::read_exact(&enc), Ok(v));
+
+  //elements which read nothing are refused instead of looping
+  #[derive(Debug, PartialEq)]
+  struct Nothing;
+  impl<'a> Codec<'a> for Nothing {
+    fn encode(&self, _: &mut Vec<u8>) {}
+    fn read(_: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      Ok(Nothing)
+    }
+  }
+  let err = BoundedVec::<U8, Nothing, 0, 4>::read_exact(&[1, 0]).unwrap_err();
+  assert_eq!(err.root(), &DecodeError::InvalidValue { offset: 1, what: "zero length element" });
+}
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "BoundedPayload body of 33 bytes is not allowed by its length prefix")]
+fn test_bounded_encode_over() {
+  use super::prefix::U8;
+  BoundedPayload::<U8, 0, 32>::new(vec![0; 33]).get_encoding();
+}
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "BoundedPayload body of 0 bytes is not allowed by its length prefix")]
+fn test_bounded_encode_under() {
+  use super::prefix::U16;
+  BoundedVec::<U16, u16, 2, 0xfffe>::new(vec![]).get_encoding();
+}
@@ -1,146 +1,105 @@
-use std::fmt::Debug;
-use std::borrow::Cow;
+#[cfg(feature = "alloc")]
+use core::fmt::Debug;
+#[cfg(feature = "alloc")]
+use core::ops::{Deref,DerefMut};
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
+use super::error::{DecodeError,EncodeError,ErrorMessage,PathSegment};
+use super::prefix::{LengthPrefixed,U8,U16,U24,U32,U64,U16Le,U24Le,U32Le,U64Le,Varint,QuicVarint};
+#[cfg(feature = "alloc")]
+use super::dump::Trace;
+
+
+///Storage behind the payload types. Borrowed or owned when `alloc`
+///is available, always borrowed without it.
+#[cfg(feature = "alloc")]
+pub type Bytes<'a> = Cow<'a,[u8]>;
+#[cfg(not(feature = "alloc"))]
+pub type Bytes<'a> = &'a [u8];
+
+///Wrap a borrowed slice as payload storage
+#[cfg(feature = "alloc")]
+#[inline(always)]
+pub fn borrowed(x: &[u8]) -> Bytes<'_> {
+  Cow::Borrowed(x)
+}
+#[cfg(not(feature = "alloc"))]
+#[inline(always)]
+pub fn borrowed(x: &[u8]) -> Bytes<'_> {
+  x
+}
 
 ///Ensure the COW borrow mutating is done.
+#[cfg(feature = "alloc")]
 #[inline(always)]
 pub fn extend(x: &[u8], bytes: &mut Vec<u8>) {
   bytes.extend_from_slice(x)
 }
-///Handle non-allocating string conversion
+///Ensure `len` bytes can be described by a length prefix
+///whose largest value is `max`.
 #[inline(always)]
-pub fn to_str<'a>(x: &'a [u8]) -> Option<&'a str> {
-  use std::str::from_utf8;
-  match from_utf8(x) {
-    Err(_) => None,
-    Ok(x) => Some(x)
-  }
-}
-
-macro_rules! payloadtraits {
-(
-  $type_with_lifetime: ty,
-  $type_for_building:expr,
-  $len_type: ty,
-  $encode_func: ident,
-  $decode_func: ident
-) => (
-  impl<'a> $type_with_lifetime {
-    pub fn new(bytes: Vec<u8>) -> Self {
-      $type_for_building(Cow::Owned(bytes))
-    }
-    pub fn from_slice(data: &'a [u8]) -> Self {
-      $type_for_building(Cow::Borrowed(data))
-    }
-    #[inline(always)]
-    pub fn len(&self) ->  usize {
-      self.0.len()
-    }
-    pub fn to_str(&'a self) -> Option<&'a str> {
-      use std::str::from_utf8;
-      match from_utf8(&self.0) {
-        Err(_) => None,
-        Ok(x) => Some(x)
-      }
-    }
-    pub fn to_slice(&'a self) -> &'a [u8] {
-      &self.0
-    }
-  }
-  impl<'a> Codec<'a> for $type_with_lifetime {
-    fn encode(&self, bytes: &mut Vec<u8>) {
-      $encode_func(self.len() as $len_type, bytes);
-      extend(&self.0,bytes);
-    }
-    fn read(r: &mut Reader<'a>) -> Option<Self> {
-      r.$decode_func()
-    }
+pub fn check_len(len: usize, max: u64) -> Result<(),EncodeError> {
+  if (len as u64) > max {
+    return Err(EncodeError::LengthOverflow { max, actual: len });
   }
-  impl<'a> From<&'a [u8]> for $type_with_lifetime {
-    fn from(x: &'a [u8]) -> Self {
-      $type_for_building(Cow::Borrowed(x))
-    }
-  }
-  impl<'a> From<Vec<u8>> for $type_with_lifetime {
-    fn from(x: Vec<u8>) -> Self {
-      $type_for_building(Cow::Owned(x))
-    }
-  }
-  impl<'a> Into<Vec<u8>> for $type_with_lifetime {
-    fn into(self) -> Vec<u8> {
-      self.0.into_owned()
-    }
-  }
-)    
+  Ok(())
+}
+///Handle non-allocating string conversion
+#[inline(always)]
+pub fn to_str(x: &[u8]) -> Option<&str> {
+  core::str::from_utf8(x).ok()
 }
 
-#[derive(Debug,PartialEq,Clone)]
-pub struct PayloadU8<'a>(pub Cow<'a,[u8]>);
-payloadtraits!(
-  PayloadU8<'a>,
-  PayloadU8,
-  u8,
-  encode_u8,
-  u8_payload
-);
-#[derive(Debug,PartialEq,Clone)]
-pub struct PayloadU16<'a>(pub Cow<'a,[u8]>);
-payloadtraits!(
-  PayloadU16<'a>,
-  PayloadU16,
-  u16,
-  encode_u16,
-  u16_payload
-);
-#[derive(Debug,PartialEq,Clone)]
-pub struct PayloadU24<'a>(pub Cow<'a,[u8]>);
-payloadtraits!(
-  PayloadU24<'a>,
-  PayloadU24,
-  u32,
-  encode_u24,
-  u24_payload
-);
-#[derive(Debug,PartialEq,Clone)]
-pub struct PayloadU32<'a>(pub Cow<'a,[u8]>);
-payloadtraits!(
-  PayloadU32<'a>,
-  PayloadU32,
-  u32,
-  encode_u32,
-  u32_payload
-);
-#[derive(Debug,PartialEq,Clone)]
-pub struct PayloadU64<'a>(pub Cow<'a,[u8]>);
-payloadtraits!(
-  PayloadU64<'a>,
-  PayloadU64,
-  u64,
-  encode_u64,
-  u64_payload
-);
-
+///Bytes with a u8 length prefix
+pub type PayloadU8<'a> = LengthPrefixed<U8, Bytes<'a>>;
+///Bytes with a big endian u16 length prefix
+pub type PayloadU16<'a> = LengthPrefixed<U16, Bytes<'a>>;
+///Bytes with a big endian u24 length prefix
+pub type PayloadU24<'a> = LengthPrefixed<U24, Bytes<'a>>;
+///Bytes with a big endian u32 length prefix
+pub type PayloadU32<'a> = LengthPrefixed<U32, Bytes<'a>>;
+///Bytes with a big endian u64 length prefix
+pub type PayloadU64<'a> = LengthPrefixed<U64, Bytes<'a>>;
+///Bytes with a little endian u16 length prefix
+pub type PayloadU16Le<'a> = LengthPrefixed<U16Le, Bytes<'a>>;
+///Bytes with a little endian u24 length prefix
+pub type PayloadU24Le<'a> = LengthPrefixed<U24Le, Bytes<'a>>;
+///Bytes with a little endian u32 length prefix
+pub type PayloadU32Le<'a> = LengthPrefixed<U32Le, Bytes<'a>>;
+///Bytes with a little endian u64 length prefix
+pub type PayloadU64Le<'a> = LengthPrefixed<U64Le, Bytes<'a>>;
+///Bytes with a LEB128 length prefix
+pub type PayloadVarint<'a> = LengthPrefixed<Varint, Bytes<'a>>;
+///Bytes with a QUIC varint length prefix
+pub type PayloadQuicVarint<'a> = LengthPrefixed<QuicVarint, Bytes<'a>>;
 
-               
+///Bytes running to the end of the reader, usually the body of an
+///enclosing length prefix
 #[derive(Debug,PartialEq,Clone)]
-pub struct Payload<'a>(pub Cow<'a,[u8]>);
+pub struct Payload<'a>(pub Bytes<'a>);
+//without alloc `Bytes` is already a reference
+#[cfg_attr(not(feature = "alloc"), allow(clippy::needless_borrow))]
 impl<'a> Payload<'a> {
+  #[cfg(feature = "alloc")]
   pub fn new(bytes: Vec<u8>) -> Self {
     Payload(Cow::Owned(bytes))
   }
   pub fn from_slice(data: &'a [u8]) -> Self {
-    Payload(Cow::Borrowed(data))
+    Payload(borrowed(data))
   }
   #[inline(always)]
   pub fn len(&self) ->  usize {
     self.0.len()
   }
+  #[inline(always)]
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
   pub fn to_str(&'a self) -> Option<&'a str> {
-    use std::str::from_utf8;
-    match from_utf8(&self.0) {
-      Err(_) => None,
-      Ok(x) => Some(x)
-    }
+    to_str(&self.0)
   }
   pub fn to_slice(&'a self) -> &'a [u8] {
     &self.0
@@ -149,30 +108,148 @@ impl<'a> Payload<'a> {
     Reader::init(&self.0)
   }
 }
+///The rest of the reader, as the body of the payload types
+#[cfg(feature = "alloc")]
+impl<'a> Codec<'a> for Bytes<'a> {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    extend(self, bytes);
+  }
+  fn exact_len(&self) -> Option<usize> {
+    Some(self.len())
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    Ok(self.len())
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: ::bytes::BufMut>(&self, buf: &mut B) {
+    buf.put_slice(self);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let len = r.left();
+    r.take(len).map(borrowed)
+  }
+}
+#[cfg(feature = "alloc")]
 impl<'a> Codec<'a> for Payload<'a> {
   fn encode(&self, bytes: &mut Vec<u8>) {
     extend(&self.0,bytes);
   }
-  fn read(r: &mut Reader<'a>) -> Option<Self> {
-    Some(Payload(Cow::Borrowed(r.rest())))
+  fn exact_len(&self) -> Option<usize> {
+    Some(self.0.len())
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    Ok(self.0.len())
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: ::bytes::BufMut>(&self, buf: &mut B) {
+    buf.put_slice(&self.0);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let len = r.left();
+    r.take(len).map(|x| Payload(borrowed(x)))
   }
 }
 impl<'a> From<&'a [u8]> for Payload<'a> {
  fn from(x: &'a [u8]) -> Self {
-    Payload(Cow::Borrowed(x))
+    Payload(borrowed(x))
   }
 }
+#[cfg(feature = "alloc")]
 impl<'a> From<Vec<u8>> for Payload<'a> {
   fn from(x: Vec<u8>) -> Self {
     Payload(Cow::Owned(x))
   }
 }
-impl<'a> Into<Vec<u8>> for Payload<'a> {
-  fn into(self) -> Vec<u8> {
-    self.0.into_owned()
+#[cfg(feature = "alloc")]
+impl<'a> From<Payload<'a>> for Vec<u8> {
+  fn from(x: Payload<'a>) -> Self {
+    x.0.into_owned()
+  }
+}
+
+
+///Byte order of multi-byte integers and length prefixes.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub enum ByteOrder {
+  #[default]
+  BigEndian,
+  LittleEndian
+}
+
+///Resource limits a `Reader` enforces while decoding untrusted
+///input. Exceeding one gives `DecodeError::LimitExceeded`.
+///
+///The default is unlimited, use `DecodeLimits::strict` or set
+///fields for input from an untrusted peer.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct DecodeLimits {
+  ///total bytes vectors and copied payloads may allocate
+  pub max_alloc: usize,
+  ///elements in any one vector
+  pub max_elements: usize,
+  ///bytes in any one length prefixed payload or vector body
+  pub max_payload_len: usize,
+  ///nesting of `sub` readers, one per length prefixed vector
+  pub max_depth: usize
+}
+
+impl DecodeLimits {
+
+  ///No limits at all
+  pub const UNLIMITED: DecodeLimits = DecodeLimits {
+    max_alloc: usize::MAX,
+    max_elements: usize::MAX,
+    max_payload_len: usize::MAX,
+    max_depth: usize::MAX
+  };
+
+  ///Limits suited to a single TLS sized message of up to 64KiB
+  pub fn strict() -> DecodeLimits {
+    DecodeLimits {
+      max_alloc: 1 << 20,
+      max_elements: 1 << 16,
+      max_payload_len: 1 << 16,
+      max_depth: 16
+    }
+  }
+}
+
+impl Default for DecodeLimits {
+  fn default() -> Self {
+    DecodeLimits::UNLIMITED
+  }
+}
+
+///Decode an unsigned integer from all of `bytes`
+fn uint_from(bytes: &[u8], order: ByteOrder) -> u64 {
+  match order {
+    ByteOrder::BigEndian => bytes.iter()
+      .fold(0u64, |acc, b| (acc << 8) | *b as u64),
+    ByteOrder::LittleEndian => bytes.iter().rev()
+      .fold(0u64, |acc, b| (acc << 8) | *b as u64),
   }
 }
 
+///One step of decoding, recorded by a traced `Reader`.
+///Offsets are absolute.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum Event {
+  Int { offset: usize, width: usize, value: u64 },
+  Bytes { offset: usize, len: usize },
+  Sub { offset: usize, len: usize },
+  Enter { offset: usize, segment: PathSegment },
+  Exit { offset: usize },
+}
+
+///A position within a `Reader`, from `Reader::checkpoint`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Checkpoint {
+  offs: usize,
+  alloc_left: usize,
+  //events recorded by a traced reader
+  #[cfg(feature = "alloc")]
+  events: usize
+}
 
 ///Reader holds a borrowed buffer. It uses this borrow to hold several
 ///slices of different length, these slices are encoded internally via
@@ -180,13 +257,50 @@ impl<'a> Into<Vec<u8>> for Payload<'a> {
 ///are stored.
 pub struct Reader<'a> {
   buf: &'a [u8],
-  offs: usize
+  offs: usize,
+  order: ByteOrder,
+  partial: bool,
+  strict: bool,
+  //offset of buf[0] within the root reader's buffer
+  base: usize,
+  limits: DecodeLimits,
+  //allocation budget left, shared with sub readers via `end_sub`
+  alloc_left: usize,
+  //number of `sub` calls between this reader and the top level
+  depth: usize,
+  //the `bytes::Bytes` buf points into, if any, so payloads
+  //can share it rather than copy
+  #[cfg(feature = "bytes")]
+  pub(crate) shared: Option<&'a ::bytes::Bytes>,
+  //records reads for an annotated dump, see `Reader::traced`
+  #[cfg(feature = "alloc")]
+  pub(crate) trace: Option<&'a Trace>
 }
+///What a `Reader` passes on to the readers made from it: where it
+///sits in the root buffer, its settings and what it shares. Lets
+///an equivalent reader be built again later over the same bytes.
+#[derive(Clone,Copy)]
+pub(crate) struct ReaderSettings<'a> {
+  order: ByteOrder,
+  strict: bool,
+  base: usize,
+  limits: DecodeLimits,
+  alloc_left: usize,
+  depth: usize,
+  #[cfg(feature = "bytes")]
+  shared: Option<&'a ::bytes::Bytes>,
+  #[cfg(feature = "alloc")]
+  trace: Option<&'a Trace>,
+  #[cfg(not(feature = "alloc"))]
+  _buf: core::marker::PhantomData<&'a [u8]>
+}
+
 impl<'a> From<&'a [u8]> for Reader<'a> {
  fn from(x: &'a [u8]) -> Self {
     Reader::init(x)
   }
 }
+#[cfg(feature = "alloc")]
 impl<'a> From<&'a Vec<u8>> for Reader<'a> {
   fn from(x: &'a Vec<u8>) -> Self {
     Reader::init(x.as_slice())
@@ -197,7 +311,44 @@ impl<'a> Reader<'a> {
   
   ///Build a new Reader by borrowing a slice
   pub fn init(bytes: &'a [u8]) -> Reader<'a> {
-    Reader { buf: bytes, offs: 0 }
+    Reader::with_order(bytes, ByteOrder::BigEndian)
+  }
+
+  ///Build a new Reader whose unsuffixed length reads use `order`
+  pub fn with_order(bytes: &'a [u8], order: ByteOrder) -> Reader<'a> {
+    Reader {
+      buf: bytes,
+      offs: 0,
+      order,
+      partial: false,
+      strict: false,
+      base: 0,
+      limits: DecodeLimits::UNLIMITED,
+      alloc_left: usize::MAX,
+      depth: 0,
+      #[cfg(feature = "bytes")]
+      shared: None,
+      #[cfg(feature = "alloc")]
+      trace: None
+    }
+  }
+
+  ///Build a Reader over the bytes received so far of a message
+  ///which may continue. Running out of input gives `Incomplete`
+  ///rather than `Truncated`, the caller should wait for more data
+  ///and decode again from the start.
+  ///
+  ///Readers made with `sub` are never partial, their length is
+  ///already known so running out of it is malformed input.
+  pub fn partial(bytes: &'a [u8]) -> Reader<'a> {
+    let mut r = Reader::init(bytes);
+    r.partial = true;
+    r
+  }
+
+  ///True if this reader was built with `partial`
+  pub fn is_partial(&self) -> bool {
+    self.partial
   }
 
   ///Return all date remaining in the buffer
@@ -206,13 +357,94 @@ impl<'a> Reader<'a> {
   }
 
   ///Take len amount of data
-  pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+  pub fn take(&mut self, len: usize) -> Result<&'a [u8],DecodeError> {
+    let offset = self.absolute_offset();
+    let ret = self.advance(len)?;
+    self.trace(Event::Bytes { offset, len });
+    Ok(ret)
+  }
+
+  ///take without recording it in the trace
+  fn advance(&mut self, len: usize) -> Result<&'a [u8],DecodeError> {
+    let ret = self.peek(len)?;
+    self.offs += len;
+    Ok(ret)
+  }
+
+  ///record `event` if this reader is traced
+  #[inline(always)]
+  fn trace(&self, event: Event) {
+    #[cfg(feature = "alloc")]
+    {
+      if let Some(trace) = self.trace {
+        trace.push(event);
+      }
+    }
+    #[cfg(not(feature = "alloc"))]
+    {
+      let _ = event;
+    }
+  }
+
+  ///Return the next len bytes without consuming them
+  pub fn peek(&self, len: usize) -> Result<&'a [u8],DecodeError> {
+    if len > self.limits.max_payload_len {
+      return Err(self.limit_exceeded("max_payload_len"));
+    }
     if self.left() < len {
-      return None
+      return Err(self.truncated(len));
     }
-    let current = self.offs;
-    self.offs += len;
-    Some(&self.buf[current .. current + len])
+    Ok(&self.buf[self.offs .. self.offs + len])
+  }
+
+  ///decode the next u8 without consuming it
+  pub fn peek_u8(&self) -> Result<usize,DecodeError> {
+    self.peek(1).map(|b| b[0] as usize)
+  }
+
+  ///decode the next u16, in the reader's byte order, without
+  ///consuming it
+  pub fn peek_u16(&self) -> Result<usize,DecodeError> {
+    self.peek(2).map(|b| uint_from(b, self.order) as usize)
+  }
+
+  ///Record the current position so it can be returned to
+  ///with `restore`
+  pub fn checkpoint(&self) -> Checkpoint {
+    Checkpoint {
+      offs: self.offs,
+      alloc_left: self.alloc_left,
+      #[cfg(feature = "alloc")]
+      events: self.trace.map_or(0, |t| t.len())
+    }
+  }
+
+  ///Rewind to a position recorded by `checkpoint` on this reader,
+  ///the allocation budget used since then is given back and the
+  ///reads since then are dropped from the trace
+  pub fn restore(&mut self, pos: Checkpoint) {
+    debug_assert!(pos.offs <= self.buf.len());
+    self.offs = pos.offs;
+    self.alloc_left = pos.alloc_left;
+    #[cfg(feature = "alloc")]
+    {
+      if let Some(trace) = self.trace {
+        trace.truncate(pos.events);
+      }
+    }
+  }
+
+  ///Run `f` on this reader, rewinding to where it started if
+  ///`f` fails so another layout can be tried
+  pub fn try_parse<T, F>(&mut self, f: F) -> Result<T,DecodeError>
+  where F: FnOnce(&mut Reader<'a>) -> Result<T,DecodeError>
+  {
+    let pos = self.checkpoint();
+    let ret = f(self);
+    if ret.is_err() {
+      self.restore(pos);
+    }
+    ret
   }
 
   ///Check if any data remains in the structure
@@ -230,188 +462,1329 @@ impl<'a> Reader<'a> {
     self.offs
   }
 
-  ///Make a reader over len which points to THIS reader's buffer
-  pub fn sub(&mut self, len: usize) -> Option<Reader<'a>> {
-    self.take(len).and_then(|bytes| Some(Reader::init(bytes)))
+  ///current offset within the buffer of the outermost reader,
+  ///which unlike `used` carries on counting inside `sub` readers
+  pub fn absolute_offset(&self) -> usize {
+    self.base + self.offs
+  }
+
+  ///Run `f`, labelling any error it returns with the field `name`.
+  ///Nested calls build up a path such as `hello.extensions[3].name`,
+  ///see `DecodeError::path`.
+  pub fn context<T, F>(&mut self, name: &'static str, f: F) -> Result<T,DecodeError>
+  where F: FnOnce(&mut Reader<'a>) -> Result<T,DecodeError>
+  {
+    self.scope(PathSegment::Field(name), f)
+  }
+
+  ///Like `context`, for element `index` of a vector
+  pub fn context_index<T, F>(&mut self, index: usize, f: F) -> Result<T,DecodeError>
+  where F: FnOnce(&mut Reader<'a>) -> Result<T,DecodeError>
+  {
+    self.scope(PathSegment::Index(index), f)
+  }
+
+  fn scope<T, F>(&mut self, segment: PathSegment, f: F) -> Result<T,DecodeError>
+  where F: FnOnce(&mut Reader<'a>) -> Result<T,DecodeError>
+  {
+    self.trace(Event::Enter { offset: self.absolute_offset(), segment });
+    let ret = f(self).map_err(|e| e.in_context(segment));
+    self.trace(Event::Exit { offset: self.absolute_offset() });
+    ret
+  }
+
+  ///Make a reader over len which points to THIS reader's buffer.
+  ///It inherits this reader's limits and allocation budget, pass
+  ///it to `end_sub` when done so what it used is accounted for.
+  pub fn sub(&mut self, len: usize) -> Result<Reader<'a>,DecodeError> {
+    if self.depth >= self.limits.max_depth {
+      return Err(self.limit_exceeded("max_depth"));
+    }
+    let base = self.absolute_offset();
+    let mut sub = self.window(len)?;
+    self.trace(Event::Sub { offset: base, len });
+    sub.depth += 1;
+    Ok(sub)
+  }
+
+  ///Run `f` one level deeper, as the elements of a vector with
+  ///no byte length are, failing if that passes `max_depth`
+  #[cfg(feature = "alloc")]
+  pub(crate) fn nested<T, F>(&mut self, f: F) -> Result<T,DecodeError>
+  where F: FnOnce(&mut Reader<'a>) -> Result<T,DecodeError>
+  {
+    if self.depth >= self.limits.max_depth {
+      return Err(self.limit_exceeded("max_depth"));
+    }
+    self.depth += 1;
+    let ret = f(self);
+    self.depth -= 1;
+    ret
+  }
+
+  ///Like `sub`, for the body of a `LengthPrefixed` value rather
+  ///than a vector. It is not traced as a sub reader and does not
+  ///count towards `max_depth`.
+  pub(crate) fn window(&mut self, len: usize) -> Result<Reader<'a>,DecodeError> {
+    let settings = self.settings();
+    let bytes = self.advance(len)?;
+    Ok(Reader::with_settings(bytes, settings))
+  }
+
+  ///Settings for a reader starting at the current position
+  pub(crate) fn settings(&self) -> ReaderSettings<'a> {
+    ReaderSettings {
+      order: self.order,
+      strict: self.strict,
+      base: self.absolute_offset(),
+      limits: self.limits,
+      alloc_left: self.alloc_left,
+      depth: self.depth,
+      #[cfg(feature = "bytes")]
+      shared: self.shared,
+      #[cfg(feature = "alloc")]
+      trace: self.trace,
+      #[cfg(not(feature = "alloc"))]
+      _buf: core::marker::PhantomData
+    }
+  }
+
+  ///Build a reader over `bytes` from `settings`, which were taken
+  ///where `bytes` starts
+  pub(crate) fn with_settings(bytes: &'a [u8], settings: ReaderSettings<'a>) -> Reader<'a> {
+    Reader {
+      buf: bytes,
+      offs: 0,
+      order: settings.order,
+      partial: false,
+      strict: settings.strict,
+      base: settings.base,
+      limits: settings.limits,
+      alloc_left: settings.alloc_left,
+      depth: settings.depth,
+      #[cfg(feature = "bytes")]
+      shared: settings.shared,
+      #[cfg(feature = "alloc")]
+      trace: settings.trace
+    }
+  }
+
+  ///Take back the allocation budget left in a reader made by `sub`.
+  ///In strict mode this fails if `sub` was not fully consumed.
+  pub fn end_sub(&mut self, sub: Reader<'a>) -> Result<(),DecodeError> {
+    if self.strict {
+      sub.finish()?;
+    }
+    self.alloc_left = sub.alloc_left;
+    Ok(())
+  }
+
+  ///Take back the allocation budget left in `sub` without the
+  ///strict check, for a sub reader given up on part way
+  #[cfg(feature = "alloc")]
+  pub(crate) fn reclaim_alloc(&mut self, sub: &Reader<'a>) {
+    self.alloc_left = sub.alloc_left;
+  }
+
+  ///Fail with TrailingData if any data remains
+  pub fn finish(&self) -> Result<(),DecodeError> {
+    if self.any_left() {
+      return Err(DecodeError::TrailingData { offset: self.absolute_offset(), left: self.left() });
+    }
+    Ok(())
+  }
+
+  ///true if `end_sub` checks sub readers were fully consumed
+  pub fn is_strict(&self) -> bool {
+    self.strict
+  }
+
+  ///turn strict mode on or off, sub readers made afterwards
+  ///inherit it
+  pub fn set_strict(&mut self, strict: bool) {
+    self.strict = strict;
+  }
+
+  ///limits enforced by this reader
+  pub fn limits(&self) -> DecodeLimits {
+    self.limits
+  }
+
+  ///change the limits, which also resets the allocation budget
+  pub fn set_limits(&mut self, limits: DecodeLimits) {
+    self.limits = limits;
+    self.alloc_left = limits.max_alloc;
+  }
+
+  ///Count `bytes` of allocation against `max_alloc`, for `Codec`
+  ///implementations which allocate while decoding
+  pub fn charge_alloc(&mut self, bytes: usize) -> Result<(),DecodeError> {
+    if bytes > self.alloc_left {
+      return Err(self.limit_exceeded("max_alloc"));
+    }
+    self.alloc_left -= bytes;
+    Ok(())
+  }
+
+  ///Push `item` onto `v`, enforcing `max_elements` and charging any
+  ///growth of `v` against `max_alloc`. The capacity is never based
+  ///on a length read from the input.
+  #[cfg(feature = "alloc")]
+  pub fn push_limited<T>(&mut self, v: &mut Vec<T>, item: T) -> Result<(),DecodeError> {
+    if v.len() >= self.limits.max_elements {
+      return Err(self.limit_exceeded("max_elements"));
+    }
+    if v.len() == v.capacity() {
+      let grow = core::cmp::max(4, v.capacity());
+      self.charge_alloc(grow.saturating_mul(core::mem::size_of::<T>()))?;
+      v.reserve_exact(grow);
+    }
+    v.push(item);
+    Ok(())
+  }
+
+  ///Read one element of a vector whose body this reader covers.
+  ///An element which consumes nothing is refused, otherwise a
+  ///vector of `()` would never reach the end of its body.
+  #[cfg(feature = "alloc")]
+  pub(crate) fn read_element<T: Codec<'a>>(&mut self) -> Result<T,DecodeError> {
+    let left = self.left();
+    let err = self.invalid("zero length element");
+    let item = T::read(self)?;
+    if self.left() == left {
+      return Err(err);
+    }
+    Ok(item)
+  }
+
+  ///build a LimitExceeded error at the current offset
+  pub fn limit_exceeded(&self, limit: &'static str) -> DecodeError {
+    DecodeError::LimitExceeded { offset: self.absolute_offset(), limit }
+  }
+
+  ///build a Truncated error for a read of `needed` bytes
+  ///at the current offset, or Incomplete for a partial reader
+  pub fn truncated(&self, needed: usize) -> DecodeError {
+    if self.partial {
+      return DecodeError::Incomplete {
+        offset: self.absolute_offset(),
+        needed: needed.saturating_sub(self.left())
+      };
+    }
+    DecodeError::Truncated {
+      offset: self.absolute_offset(),
+      needed,
+      available: self.left()
+    }
+  }
+
+  ///build an InvalidValue error at the current offset
+  pub fn invalid(&self, what: &'static str) -> DecodeError {
+    DecodeError::InvalidValue { offset: self.absolute_offset(), what }
+  }
+
+  ///build a Custom error at the current offset, for use
+  ///by `Codec` implementations outside this crate
+  pub fn custom<M: Into<ErrorMessage>>(&self, msg: M) -> DecodeError {
+    DecodeError::Custom { offset: self.absolute_offset(), msg: msg.into() }
+  }
+
+  ///byte order used by `read_u16` and the other unsuffixed
+  ///length reads
+  pub fn order(&self) -> ByteOrder {
+    self.order
+  }
+
+  ///change the byte order used by unsuffixed length reads
+  pub fn set_order(&mut self, order: ByteOrder) {
+    self.order = order;
+  }
+
+  ///decode a `width` byte unsigned integer at the current offset
+  fn read_uint(&mut self, width: usize, order: ByteOrder) -> Result<u64,DecodeError> {
+    let offset = self.absolute_offset();
+    let value = uint_from(self.advance(width)?, order);
+    self.trace(Event::Int { offset, width, value });
+    Ok(value)
+  }
+
+  ///decode a `width` byte length, which must fit in a usize
+  fn read_len(&mut self, width: usize, order: ByteOrder) -> Result<usize,DecodeError> {
+    let start = self.offs;
+    let ret = self.read_uint(width, order)?;
+    if ret > (usize::MAX as u64) {
+      self.offs = start;
+      return Err(DecodeError::LengthOverflow { offset: self.absolute_offset(), len: ret });
+    }
+    Ok(ret as usize)
   }
 
   ///decode a u8 length from the current offset
-  pub fn read_u8(&mut self) -> Option<usize> {
-    if self.left() < 1 {
-      return None;  
-    }
-    let arg: u8 = self.buf[self.offs].clone();
-    self.offs += 1;
-    Some(arg as usize)
-  }
-
-  ///decode a u16 length at the current offset
-  pub fn read_u16(&mut self) -> Option<usize> {
-    if self.left() < 2 {
-        return None;
-    }
-    let arg0 = self.buf[self.offs].clone() as u16;
-    let arg1 = self.buf[self.offs+1].clone() as u16;
-    let arg0 = arg0 << 8;
-    self.offs += 2;
-    let ret = arg0|arg1;
-    Some(ret as usize)
-  }
-
-  ///decode a u24 length at the current offset
-  pub fn read_u24(&mut self) -> Option<usize> {
-    if self.left() < 3 {
-        return None;
-    }
-    let arg0 = self.buf[self.offs].clone() as u32;
-    let arg1 = self.buf[self.offs+1].clone() as u32;
-    let arg2 = self.buf[self.offs+2].clone() as u32;
-    let arg0 = arg0 << 16;
-    let arg1 = arg1 << 8;
-    self.offs += 3;
-    let ret = arg0 | arg1 | arg2;
-    Some(ret as usize)
-  }
-
-  ///decode a u32 length at the current offset
-  pub fn read_u32(&mut self) -> Option<usize> {
-    if self.left() < 4 {
-        return None;
-    }
-    let arg0 = self.buf[self.offs].clone() as u32;
-    let arg1 = self.buf[self.offs+1].clone() as u32;
-    let arg2 = self.buf[self.offs+2].clone() as u32;
-    let arg3 = self.buf[self.offs+3].clone() as u32;
-    let arg0 = arg0 << 24;
-    let arg1 = arg1 << 16;
-    let arg2 = arg2 << 8;
-    self.offs += 4;
-    let ret = arg0 | arg1 | arg2 | arg3;
-    Some(ret as usize)
-  }
-
-  ///decode a u64 length at the current offset
-  pub fn read_u64(&mut self) -> Option<usize> {
-    if self.left() < 8 {
-        return None;
-    }
-    let arg0 = self.buf[self.offs].clone() as u64;
-    let arg1 = self.buf[self.offs+1].clone() as u64;
-    let arg2 = self.buf[self.offs+2].clone() as u64;
-    let arg3 = self.buf[self.offs+3].clone() as u64;
-    let arg4 = self.buf[self.offs+4].clone() as u64;
-    let arg5 = self.buf[self.offs+5].clone() as u64;
-    let arg6 = self.buf[self.offs+6].clone() as u64;
-    let arg7 = self.buf[self.offs+7].clone() as u64;
-    let arg0 = arg0 << 56;
-    let arg1 = arg1 << 48;
-    let arg2 = arg2 << 40;
-    let arg3 = arg3 << 32;
-    let arg4 = arg4 << 24;
-    let arg5 = arg5 << 16;
-    let arg6 = arg6 << 8;
-    self.offs += 8;
-    let ret = arg0 | arg1 | arg2 | arg3 | arg4 | arg5 | arg6 | arg7;
-    Some(ret as usize)
+  pub fn read_u8(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(1, ByteOrder::BigEndian)
+  }
+
+  ///decode a u16 length at the current offset, in the reader's byte order
+  pub fn read_u16(&mut self) -> Result<usize,DecodeError> {
+    let order = self.order;
+    self.read_len(2, order)
+  }
+
+  ///decode a big endian u16 length at the current offset
+  pub fn read_u16_be(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(2, ByteOrder::BigEndian)
+  }
+
+  ///decode a little endian u16 length at the current offset
+  pub fn read_u16_le(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(2, ByteOrder::LittleEndian)
+  }
+
+  ///decode a u24 length at the current offset, in the reader's byte order
+  pub fn read_u24(&mut self) -> Result<usize,DecodeError> {
+    let order = self.order;
+    self.read_len(3, order)
+  }
+
+  ///decode a big endian u24 length at the current offset
+  pub fn read_u24_be(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(3, ByteOrder::BigEndian)
+  }
+
+  ///decode a little endian u24 length at the current offset
+  pub fn read_u24_le(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(3, ByteOrder::LittleEndian)
+  }
+
+  ///decode a u32 length at the current offset, in the reader's byte order
+  pub fn read_u32(&mut self) -> Result<usize,DecodeError> {
+    let order = self.order;
+    self.read_len(4, order)
+  }
+
+  ///decode a big endian u32 length at the current offset
+  pub fn read_u32_be(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(4, ByteOrder::BigEndian)
+  }
+
+  ///decode a little endian u32 length at the current offset
+  pub fn read_u32_le(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(4, ByteOrder::LittleEndian)
+  }
+
+  ///decode a u64 length at the current offset, in the reader's byte order
+  pub fn read_u64(&mut self) -> Result<usize,DecodeError> {
+    let order = self.order;
+    self.read_len(8, order)
+  }
+
+  ///decode a big endian u64 length at the current offset
+  pub fn read_u64_be(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(8, ByteOrder::BigEndian)
+  }
+
+  ///decode a little endian u64 length at the current offset
+  pub fn read_u64_le(&mut self) -> Result<usize,DecodeError> {
+    self.read_len(8, ByteOrder::LittleEndian)
   }
 
   ///decode a u8 length (if possible)
   ///and return a slice that long
   ///that will start right after the 1 length byte
-  pub fn u8_encoded_slice(&mut self) -> Option<&'a [u8]> {
-    let len = try_ret!(self.read_u8());
+  pub fn u8_encoded_slice(&mut self) -> Result<&'a [u8],DecodeError> {
+    let len = self.read_u8()?;
     self.take(len)
   }
 
   ///decode a u16 length (if possible)
   ///and return a slice that long 
   ///that will start right after the 2 length bytes
-  pub fn u16_encoded_slice(&mut self) -> Option<&'a [u8]> {
-    let len = try_ret!(self.read_u16());
+  pub fn u16_encoded_slice(&mut self) -> Result<&'a [u8],DecodeError> {
+    let len = self.read_u16()?;
     self.take(len)
   }
 
   ///decode a u24 length (if possible)
   ///and return a slice that long
   ///that will start right after the 3 length bytes
-  pub fn u24_encoded_slice(&mut self) -> Option<&'a [u8]> {
-    let len = try_ret!(self.read_u24());
+  pub fn u24_encoded_slice(&mut self) -> Result<&'a [u8],DecodeError> {
+    let len = self.read_u24()?;
     self.take(len)
   }
 
   ///decode a u32 length (if possible)
   ///and return a slice that long
   ///that will start right after the 4 length bytes
-  pub fn u32_encoded_slice(&mut self) -> Option<&'a [u8]> {
-    let len = try_ret!(self.read_u32());
+  pub fn u32_encoded_slice(&mut self) -> Result<&'a [u8],DecodeError> {
+    let len = self.read_u32()?;
     self.take(len)
   }
 
   ///decode a u64 length (if possible)
   ///and return a slice that long
   ///that will start right after the 8 length bytes
-  pub fn u64_encoded_slice(&mut self) -> Option<&'a [u8]> {
-    let len = try_ret!(self.read_u64());
+  pub fn u64_encoded_slice(&mut self) -> Result<&'a [u8],DecodeError> {
+    let len = self.read_u64()?;
     self.take(len)
   }
 
   ///return the remaining data in buffer as a PayLoad type
-  pub fn payload(&mut self) -> Option<Payload<'a>> {
-    Some(Payload(Cow::Borrowed(self.rest())))
+  pub fn payload(&mut self) -> Result<Payload<'a>,DecodeError> {
+    Ok(Payload(borrowed(self.rest())))
   }
   ///decode a u8 length (if that is possible)
   ///and return a PayloadU8 type that contains
   ///a slice of that length
-  pub fn u8_payload(&mut self) -> Option<PayloadU8<'a>> {
-    let slice = try_ret!(self.u8_encoded_slice());
-    Some(PayloadU8(Cow::Borrowed(slice)))
+  pub fn u8_payload(&mut self) -> Result<PayloadU8<'a>,DecodeError> {
+    let slice = self.u8_encoded_slice()?;
+    Ok(PayloadU8::from_slice(slice))
   }
   ///decode a u16 length (if that is possible)
   ///and return a PayloadU16 type that contains
   ///a slice of that length
-  pub fn u16_payload(&mut self) -> Option<PayloadU16<'a>> {
-    let slice = try_ret!(self.u16_encoded_slice());
-    Some(PayloadU16(Cow::Borrowed(slice)))
+  pub fn u16_payload(&mut self) -> Result<PayloadU16<'a>,DecodeError> {
+    let slice = self.u16_encoded_slice()?;
+    Ok(PayloadU16::from_slice(slice))
   }
   ///decode a u24 length (if that is possible)
   ///and return a PayloadU24 type that contains
   ///a slice of that length
-  pub fn u24_payload(&mut self) -> Option<PayloadU24<'a>> {
-    let slice = try_ret!(self.u24_encoded_slice());
-    Some(PayloadU24(Cow::Borrowed(slice)))
+  pub fn u24_payload(&mut self) -> Result<PayloadU24<'a>,DecodeError> {
+    let slice = self.u24_encoded_slice()?;
+    Ok(PayloadU24::from_slice(slice))
   }
   ///decode a u32 length (if that is possible)
   ///and return a PayloadU32 type that contains
   ///a slice of that length
-  pub fn u32_payload(&mut self) -> Option<PayloadU32<'a>> {
-    let slice = try_ret!(self.u32_encoded_slice());
-    Some(PayloadU32(Cow::Borrowed(slice)))
+  pub fn u32_payload(&mut self) -> Result<PayloadU32<'a>,DecodeError> {
+    let slice = self.u32_encoded_slice()?;
+    Ok(PayloadU32::from_slice(slice))
   }
   ///decode a u64 length (if that is possible)
   ///and return a PayloadU64 type that contains
   ///a slice of that length
-  pub fn u64_payload(&mut self) -> Option<PayloadU64<'a>> {
-    let slice = try_ret!(self.u64_encoded_slice());
-    Some(PayloadU64(Cow::Borrowed(slice)))
+  pub fn u64_payload(&mut self) -> Result<PayloadU64<'a>,DecodeError> {
+    let slice = self.u64_encoded_slice()?;
+    Ok(PayloadU64::from_slice(slice))
   }
 }
 
+///Writer is the encoding counterpart of Reader. It appends onto a
+///borrowed buffer, and can reserve space for a length prefix which
+///is filled in once the body following it has been written. Nested
+///codecs write straight into the parent buffer so no temporary
+///vectors are built.
+///
+///Writer derefs to the underlying `Vec<u8>`, so `Codec::encode` can
+///be handed a `&mut Writer` directly.
+#[cfg(feature = "alloc")]
+pub struct Writer<'a> {
+  buf: &'a mut Vec<u8>,
+  order: ByteOrder
+}
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a mut Vec<u8>> for Writer<'a> {
+  fn from(x: &'a mut Vec<u8>) -> Self {
+    Writer::init(x)
+  }
+}
+#[cfg(feature = "alloc")]
+impl<'a> Deref for Writer<'a> {
+  type Target = Vec<u8>;
+  fn deref(&self) -> &Vec<u8> {
+    self.buf
+  }
+}
+#[cfg(feature = "alloc")]
+impl<'a> DerefMut for Writer<'a> {
+  fn deref_mut(&mut self) -> &mut Vec<u8> {
+    self.buf
+  }
+}
+
+///Write `v` into every byte of `bytes` using `order`.
+pub(crate) fn put_uint(v: u64, bytes: &mut [u8], order: ByteOrder) {
+  let width = bytes.len();
+  for (i, b) in bytes.iter_mut().enumerate() {
+    let shift = match order {
+      ByteOrder::BigEndian => 8 * (width - 1 - i),
+      ByteOrder::LittleEndian => 8 * i,
+    };
+    *b = (v >> shift) as u8;
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Writer<'a> {
+
+  ///Build a new Writer which appends onto a vector
+  pub fn init(bytes: &'a mut Vec<u8>) -> Writer<'a> {
+    Writer::with_order(bytes, ByteOrder::BigEndian)
+  }
+
+  ///Build a new Writer whose integers and length prefixes use `order`
+  pub fn with_order(bytes: &'a mut Vec<u8>, order: ByteOrder) -> Writer<'a> {
+    Writer { buf: bytes, order }
+  }
+
+  ///byte order used for integers and length prefixes
+  pub fn order(&self) -> ByteOrder {
+    self.order
+  }
+
+  ///Total length of the underlying buffer
+  pub fn used(&self) -> usize {
+    self.buf.len()
+  }
+
+  ///Append the low `width` bytes of `v` in the writer's byte order
+  fn write_uint(&mut self, v: u64, width: usize) {
+    let start = self.buf.len();
+    self.buf.resize(start + width, 0);
+    put_uint(v, &mut self.buf[start ..], self.order);
+  }
+
+  ///encode a u8
+  pub fn write_u8(&mut self, v: u8) {
+    self.buf.push(v);
+  }
+
+  ///encode a u16 in the writer's byte order
+  pub fn write_u16(&mut self, v: u16) {
+    self.write_uint(v as u64, 2);
+  }
+
+  ///encode the low 3 bytes of v in the writer's byte order
+  pub fn write_u24(&mut self, v: u32) {
+    self.write_uint(v as u64, 3);
+  }
+
+  ///encode a u32 in the writer's byte order
+  pub fn write_u32(&mut self, v: u32) {
+    self.write_uint(v as u64, 4);
+  }
+
+  ///encode a u64 in the writer's byte order
+  pub fn write_u64(&mut self, v: u64) {
+    self.write_uint(v, 8);
+  }
+
+  ///Reserve `width` bytes, run `f`, then back-patch the number
+  ///of bytes `f` wrote into the reserved slot.
+  fn prefixed<F>(&mut self, width: usize, max: u64, f: F)
+  where F: FnOnce(&mut Writer)
+  {
+    let start = self.buf.len();
+    self.buf.resize(start + width, 0);
+    f(&mut Writer::with_order(self.buf, self.order));
+    let len = (self.buf.len() - start - width) as u64;
+    debug_assert!(len <= max);
+    put_uint(len, &mut self.buf[start .. start + width], self.order);
+  }
+
+  ///Like `prefixed` but `f` may fail, and a body too long for the
+  ///prefix is reported rather than written. On error the buffer is
+  ///truncated back to where the prefix began.
+  fn try_prefixed<F>(&mut self, width: usize, max: u64, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    let start = self.buf.len();
+    self.buf.resize(start + width, 0);
+    let res = f(&mut Writer::with_order(self.buf, self.order))
+      .and_then(|()| {
+        let len = self.buf.len() - start - width;
+        check_len(len, max).map(|()| len)
+      });
+    match res {
+      Ok(len) => {
+        put_uint(len as u64, &mut self.buf[start .. start + width], self.order);
+        Ok(())
+      }
+      Err(e) => {
+        self.buf.truncate(start);
+        Err(e)
+      }
+    }
+  }
+
+  ///Write a u8 length prefix followed by whatever `f` writes
+  pub fn u8_prefixed<F: FnOnce(&mut Writer)>(&mut self, f: F) {
+    self.prefixed(1, 0xff, f)
+  }
+
+  ///Write a u16 length prefix followed by whatever `f` writes
+  pub fn u16_prefixed<F: FnOnce(&mut Writer)>(&mut self, f: F) {
+    self.prefixed(2, 0xffff, f)
+  }
+
+  ///Write a u24 length prefix followed by whatever `f` writes
+  pub fn u24_prefixed<F: FnOnce(&mut Writer)>(&mut self, f: F) {
+    self.prefixed(3, 0x00ff_ffff, f)
+  }
+
+  ///Write a u32 length prefix followed by whatever `f` writes
+  pub fn u32_prefixed<F: FnOnce(&mut Writer)>(&mut self, f: F) {
+    self.prefixed(4, 0xffff_ffff, f)
+  }
+
+  ///Write a u64 length prefix followed by whatever `f` writes
+  pub fn u64_prefixed<F: FnOnce(&mut Writer)>(&mut self, f: F) {
+    self.prefixed(8, u64::MAX, f)
+  }
+
+  ///Checked version of `u8_prefixed`
+  pub fn try_u8_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    self.try_prefixed(1, 0xff, f)
+  }
+
+  ///Checked version of `u16_prefixed`
+  pub fn try_u16_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    self.try_prefixed(2, 0xffff, f)
+  }
+
+  ///Checked version of `u24_prefixed`
+  pub fn try_u24_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    self.try_prefixed(3, 0x00ff_ffff, f)
+  }
+
+  ///Checked version of `u32_prefixed`
+  pub fn try_u32_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    self.try_prefixed(4, 0xffff_ffff, f)
+  }
+
+  ///Checked version of `u64_prefixed`
+  pub fn try_u64_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut Writer) -> Result<(),EncodeError>
+  {
+    self.try_prefixed(8, u64::MAX, f)
+  }
+}
+///SliceWriter encodes into a fixed buffer without allocating. It
+///offers the same integer and length prefix helpers as Writer,
+///every write fails with `BufferFull` once the slice is exhausted.
+pub struct SliceWriter<'a> {
+  buf: &'a mut [u8],
+  offs: usize,
+  order: ByteOrder
+}
+impl<'a> From<&'a mut [u8]> for SliceWriter<'a> {
+  fn from(x: &'a mut [u8]) -> Self {
+    SliceWriter::init(x)
+  }
+}
+
+impl<'a> SliceWriter<'a> {
+
+  ///Build a new SliceWriter which fills `bytes` from the start
+  pub fn init(bytes: &'a mut [u8]) -> SliceWriter<'a> {
+    SliceWriter::with_order(bytes, ByteOrder::BigEndian)
+  }
+
+  ///Build a new SliceWriter whose integers and length prefixes use `order`
+  pub fn with_order(bytes: &'a mut [u8], order: ByteOrder) -> SliceWriter<'a> {
+    SliceWriter { buf: bytes, offs: 0, order }
+  }
+
+  ///byte order used for integers and length prefixes
+  pub fn order(&self) -> ByteOrder {
+    self.order
+  }
+
+  ///get length of data written so far
+  pub fn used(&self) -> usize {
+    self.offs
+  }
+
+  ///get length of space remaining
+  pub fn left(&self) -> usize {
+    self.buf.len() - self.offs
+  }
+
+  ///the bytes written so far
+  pub fn written(&self) -> &[u8] {
+    &self.buf[.. self.offs]
+  }
+
+  ///consume the writer, returning the bytes written
+  pub fn into_written(self) -> &'a [u8] {
+    &self.buf[.. self.offs]
+  }
+
+  ///claim the next `len` bytes of the buffer
+  fn reserve(&mut self, len: usize) -> Result<&mut [u8],EncodeError> {
+    if self.left() < len {
+      return Err(EncodeError::BufferFull { needed: len, available: self.left() });
+    }
+    let current = self.offs;
+    self.offs += len;
+    Ok(&mut self.buf[current .. current + len])
+  }
+
+  ///copy `data` into the buffer
+  pub fn put_slice(&mut self, data: &[u8]) -> Result<(),EncodeError> {
+    self.reserve(data.len())?.copy_from_slice(data);
+    Ok(())
+  }
+
+  ///Write the low `width` bytes of `v` in the writer's byte order
+  fn write_uint(&mut self, v: u64, width: usize) -> Result<(),EncodeError> {
+    let order = self.order;
+    put_uint(v, self.reserve(width)?, order);
+    Ok(())
+  }
+
+  ///encode a u8
+  pub fn write_u8(&mut self, v: u8) -> Result<(),EncodeError> {
+    self.write_uint(v as u64, 1)
+  }
+
+  ///encode a u16 in the writer's byte order
+  pub fn write_u16(&mut self, v: u16) -> Result<(),EncodeError> {
+    self.write_uint(v as u64, 2)
+  }
+
+  ///encode the low 3 bytes of v in the writer's byte order
+  pub fn write_u24(&mut self, v: u32) -> Result<(),EncodeError> {
+    self.write_uint(v as u64, 3)
+  }
+
+  ///encode a u32 in the writer's byte order
+  pub fn write_u32(&mut self, v: u32) -> Result<(),EncodeError> {
+    self.write_uint(v as u64, 4)
+  }
+
+  ///encode a u64 in the writer's byte order
+  pub fn write_u64(&mut self, v: u64) -> Result<(),EncodeError> {
+    self.write_uint(v, 8)
+  }
+
+  ///Reserve `width` bytes, run `f`, then back-patch the number of
+  ///bytes `f` wrote. On error the writer is rewound to the prefix.
+  fn prefixed<F>(&mut self, width: usize, max: u64, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    let start = self.offs;
+    self.reserve(width)?;
+    let mut sub = SliceWriter { buf: &mut *self.buf, offs: self.offs, order: self.order };
+    let res = f(&mut sub)
+      .and_then(|()| {
+        let len = sub.offs - start - width;
+        check_len(len, max).map(|()| (len, sub.offs))
+      });
+    match res {
+      Ok((len, end)) => {
+        put_uint(len as u64, &mut self.buf[start .. start + width], self.order);
+        self.offs = end;
+        Ok(())
+      }
+      Err(e) => {
+        self.offs = start;
+        Err(e)
+      }
+    }
+  }
+
+  ///Write a u8 length prefix followed by whatever `f` writes
+  pub fn u8_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    self.prefixed(1, 0xff, f)
+  }
+
+  ///Write a u16 length prefix followed by whatever `f` writes
+  pub fn u16_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    self.prefixed(2, 0xffff, f)
+  }
+
+  ///Write a u24 length prefix followed by whatever `f` writes
+  pub fn u24_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    self.prefixed(3, 0x00ff_ffff, f)
+  }
+
+  ///Write a u32 length prefix followed by whatever `f` writes
+  pub fn u32_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    self.prefixed(4, 0xffff_ffff, f)
+  }
+
+  ///Write a u64 length prefix followed by whatever `f` writes
+  pub fn u64_prefixed<F>(&mut self, f: F) -> Result<(),EncodeError>
+  where F: FnOnce(&mut SliceWriter) -> Result<(),EncodeError>
+  {
+    self.prefixed(8, u64::MAX, f)
+  }
+}
+#[test]
+fn test_slice_writer() {
+  let mut buf = [0u8; 12];
+  let mut w = SliceWriter::init(&mut buf);
+  w.write_u8(0xAA).unwrap();
+  w.u16_prefixed(|w| {
+    w.write_u16(0x0102)?;
+    w.u8_prefixed(|w| w.put_slice(b"abc"))
+  }).unwrap();
+  assert_eq!(w.written(), &[0xAA, 0,6, 1,2, 3,b'a',b'b',b'c']);
+
+  //a body which does not fit rewinds to before its prefix
+  assert_eq!(w.u16_prefixed(|w| w.put_slice(b"xyz")),
+    Err(EncodeError::BufferFull { needed: 3, available: 1 }));
+  assert_eq!(w.used(), 9);
+  assert_eq!(w.u8_prefixed(|w| w.put_slice(&[0u8; 2])), Ok(()));
+  assert_eq!(w.write_u8(1), Err(EncodeError::BufferFull { needed: 1, available: 0 }));
+  assert_eq!(w.into_written().len(), 12);
+
+  //the output reads back with Reader, little endian too
+  let mut buf = [0u8; 8];
+  let mut w = SliceWriter::with_order(&mut buf, ByteOrder::LittleEndian);
+  w.u32_prefixed(|w| w.write_u24(0x010203)).unwrap();
+  let out = w.into_written();
+  assert_eq!(out, &[3,0,0,0, 3,2,1]);
+  let mut r = Reader::with_order(out, ByteOrder::LittleEndian);
+  let len = r.read_u32_le().unwrap();
+  let mut sub = r.sub(len).unwrap();
+  assert_eq!(sub.read_u24().unwrap(), 0x010203);
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_writer_prefixes() {
+
+  //nested prefixes are filled in once each scope closes
+  let mut bytes = vec![0xAAu8];
+  {
+    let mut w = Writer::init(&mut bytes);
+    w.u16_prefixed(|w| {
+      w.push(1);
+      w.u8_prefixed(|w| {
+        PayloadU24::from_slice(b"abc").encode(w);
+      });
+      w.u24_prefixed(|_| {});
+    });
+    assert_eq!(w.used(), 14);
+  }
+  assert_eq!(bytes, vec![0xAA, 0,11, 1, 6, 0,0,3,b'a',b'b',b'c', 0,0,0]);
+
+  let mut r = Reader::init(&bytes[1..]);
+  let len = r.read_u16().unwrap();
+  let mut outer = r.sub(len).unwrap();
+  assert_eq!(outer.read_u8().unwrap(), 1);
+  let inner: PayloadU8 = outer.u8_payload().unwrap();
+  assert_eq!(inner.len(), 6);
+  assert_eq!(outer.u24_payload().unwrap().len(), 0);
+  assert!(!outer.any_left());
+
+  //u32 and u64 prefixes
+  let mut bytes = Vec::new();
+  Writer::init(&mut bytes).u32_prefixed(|w| w.extend_from_slice(b"xy"));
+  Writer::init(&mut bytes).u64_prefixed(|w| w.extend_from_slice(b"z"));
+  assert_eq!(bytes, vec![0,0,0,2,b'x',b'y',0,0,0,0,0,0,0,1,b'z']);
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_byte_order() {
+  let data = [0x01u8,0x02, 0x01,0x02,0x03, 0x01,0x02,0x03,0x04];
+
+  //explicit reads ignore the reader's mode
+  let mut r = Reader::init(&data);
+  assert_eq!(r.order(), ByteOrder::BigEndian);
+  assert_eq!(r.read_u16_le().unwrap(), 0x0201);
+  assert_eq!(r.read_u24_be().unwrap(), 0x010203);
+  assert_eq!(r.read_u32_le().unwrap(), 0x04030201);
+
+  //unsuffixed reads follow the mode, and sub readers inherit it
+  let mut r = Reader::with_order(&data, ByteOrder::LittleEndian);
+  assert_eq!(r.read_u16().unwrap(), 0x0201);
+  let mut sub = r.sub(7).unwrap();
+  assert_eq!(sub.order(), ByteOrder::LittleEndian);
+  assert_eq!(sub.read_u24().unwrap(), 0x030201);
+  sub.set_order(ByteOrder::BigEndian);
+  assert_eq!(sub.read_u32().unwrap(), 0x01020304);
+  assert_eq!(sub.read_u64_le(), Err(DecodeError::Truncated { offset: 9, needed: 8, available: 0 }));
+
+  //little endian payload types round trip
+  let p = PayloadU32Le::from_slice(b"riff");
+  let enc = p.get_encoding();
+  assert_eq!(enc, vec![4,0,0,0,b'r',b'i',b'f',b'f']);
+  assert_eq!(PayloadU32Le::read(&mut Reader::init(&enc)).unwrap(), p);
+  assert_eq!(PayloadU16Le::new(vec![0u8; 0x102]).get_encoding()[..2], [0x02,0x01]);
+  assert_eq!(PayloadU24Le::from_slice(b"a").get_encoding(), vec![1,0,0,b'a']);
+  assert_eq!(PayloadU64Le::from_slice(b"a").get_encoding(), vec![1,0,0,0,0,0,0,0,b'a']);
+
+  //payload types keep their declared order whatever the reader mode
+  let mut r = Reader::with_order(&enc, ByteOrder::LittleEndian);
+  assert!(PayloadU32::read(&mut r).is_err());
+
+  //writers apply their order to integers and prefixes
+  let mut bytes = Vec::new();
+  {
+    let mut w = Writer::with_order(&mut bytes, ByteOrder::LittleEndian);
+    w.write_u16(0x0102);
+    w.u24_prefixed(|w| w.write_u32(0x0a0b0c0d));
+  }
+  assert_eq!(bytes, vec![0x02,0x01, 4,0,0, 0x0d,0x0c,0x0b,0x0a]);
+  let mut r = Reader::with_order(&bytes, ByteOrder::LittleEndian);
+  assert_eq!(r.read_u16().unwrap(), 0x0102);
+  assert_eq!(r.u24_encoded_slice().unwrap(), &[0x0d,0x0c,0x0b,0x0a]);
+}
+
 /// Things we can encode and read from a Reader.
+///
+/// Only `encode` and `read` are required. A type whose encoding
+/// varies in length should also override `exact_len`, and
+/// `try_encoded_len` if it has length prefixes. Without them
+/// `get_encoding` and varint length prefixes size the encoding
+/// after writing it, and `encoded_len` encodes into a scratch
+/// buffer. Derived types and the types in this crate override them.
+#[cfg(feature = "alloc")]
 pub trait Codec<'a>: Debug + Sized {
 
   /// Encode yourself by appending onto `bytes`.
   fn encode(&self, bytes: &mut Vec<u8>);
+
+  /// Encode yourself by appending onto `bytes`, failing
+  /// if any length prefix would overflow. Types which
+  /// contain no length prefixes need not override this.
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    self.encode(bytes);
+    Ok(())
+  }
   
+  /// Length of every encoding of this type, if it is
+  /// always the same. Bounded vectors of it must have a
+  /// length which is a multiple of this.
+  const FIXED_LEN: Option<usize> = None;
+
   /// Read one of these from the front of `bytes` and
-  /// return it.
-  fn read(r: &mut Reader<'a>) -> Option<Self>;
+  /// return it, or describe why it could not be read.
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError>;
+
+  /// Read one of these from all of `bytes`, failing with
+  /// `TrailingData` if anything is left over.
+  fn read_exact(bytes: &'a [u8]) -> Result<Self,DecodeError> {
+    Self::read_exact_with_limits(bytes, DecodeLimits::UNLIMITED)
+  }
+
+  /// `read_exact` for untrusted input, enforcing `limits`.
+  fn read_exact_with_limits(bytes: &'a [u8], limits: DecodeLimits) -> Result<Self,DecodeError> {
+    let mut r = Reader::init(bytes);
+    r.set_limits(limits);
+    let ret = Self::read(&mut r)?;
+    r.finish()?;
+    Ok(ret)
+  }
+
+  /// Number of bytes `encode()` appends, if it can be worked
+  /// out without encoding. `get_encoding` and length prefixes
+  /// use it to reserve space up front, and debug builds check
+  /// it against what was written. The default is `FIXED_LEN`.
+  fn exact_len(&self) -> Option<usize> {
+    Self::FIXED_LEN
+  }
+
+  /// Number of bytes `encode()` appends. This is `exact_len`,
+  /// or else the length of an encoding into a scratch buffer.
+  fn encoded_len(&self) -> usize {
+    match self.exact_len() {
+      Some(len) => len,
+      None => {
+        let mut scratch = Vec::new();
+        self.encode(&mut scratch);
+        scratch.len()
+      }
+    }
+  }
 
   /// Convenience function to get the results of `encode()`.
   fn get_encoding(&self) -> Vec<u8> {
-    let mut ret = Vec::new();
+    let len = self.exact_len();
+    let mut ret = Vec::with_capacity(len.unwrap_or(0));
     self.encode(&mut ret);
+    if let Some(len) = len {
+      check_encoded_len::<Self>(len, ret.len());
+    }
     ret
   }
+
+  /// Convenience function to get the results of `try_encode()`.
+  fn try_get_encoding(&self) -> Result<Vec<u8>,EncodeError> {
+    let len = self.exact_len();
+    let mut ret = Vec::with_capacity(len.unwrap_or(0));
+    self.try_encode(&mut ret)?;
+    if let Some(len) = len {
+      check_encoded_len::<Self>(len, ret.len());
+    }
+    Ok(ret)
+  }
+
+  /// Number of bytes `try_encode()` appends, or the error it
+  /// would give. The default encodes into a scratch buffer,
+  /// types with length prefixes should check them directly.
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    let mut scratch = Vec::new();
+    self.try_encode(&mut scratch)?;
+    Ok(scratch.len())
+  }
+
+  /// Encode yourself into any `BufMut`, such as a `BytesMut`
+  /// bound for the network. The default goes through a `Vec`,
+  /// the types in this crate and derived types write directly.
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: ::bytes::BufMut>(&self, buf: &mut B) {
+    buf.put_slice(&self.get_encoding());
+  }
+
+  /// Checked version of `encode_buf`, nothing is written on error.
+  /// Types with an `exact_len` are checked with `try_encoded_len`
+  /// before `encode_buf` writes, others go through a `Vec`.
+  #[cfg(feature = "bytes")]
+  fn try_encode_buf<B: ::bytes::BufMut>(&self, buf: &mut B) -> Result<(),EncodeError> {
+    if self.exact_len().is_none() {
+      buf.put_slice(&self.try_get_encoding()?);
+      return Ok(());
+    }
+    self.try_encoded_len()?;
+    self.encode_buf(buf);
+    Ok(())
+  }
+}
+
+///Debug builds check `exact_len` against what was written
+#[cfg(feature = "alloc")]
+#[inline(always)]
+fn check_encoded_len<T>(expected: usize, actual: usize) {
+  debug_assert!(expected == actual, "{}::exact_len() gave {} but encode() wrote {} bytes",
+    core::any::type_name::<T>(), expected, actual);
+}
+
+///Total encoded length of `items`
+#[cfg(feature = "alloc")]
+pub(crate) fn items_len<'a, T: Codec<'a>>(items: &[T]) -> usize {
+  match T::FIXED_LEN {
+    Some(len) => len * items.len(),
+    None => items.iter().map(Codec::encoded_len).sum()
+  }
+}
+
+///Total `exact_len` of `items`, if each has one
+#[cfg(feature = "alloc")]
+pub fn items_exact_len<'a, T: Codec<'a>>(items: &[T]) -> Option<usize> {
+  match T::FIXED_LEN {
+    Some(len) => Some(len * items.len()),
+    None => items.iter().try_fold(0, |len, i| Some(len + i.exact_len()?))
+  }
+}
+
+///Total `try_encoded_len` of `items`
+#[cfg(feature = "alloc")]
+pub(crate) fn try_items_len<'a, T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  items.iter().try_fold(0, |len, i| Ok(len + i.try_encoded_len()?))
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_decode_errors() {
+
+  //running out of data reports where and by how much
+  let data = [0u8,5,1,2];
+  let mut r = Reader::init(&data);
+  assert_eq!(r.read_u8().unwrap(), 0);
+  assert_eq!(r.u8_encoded_slice(), Err(DecodeError::Truncated {
+    offset: 2,
+    needed: 5,
+    available: 2
+  }));
+
+  //length prefix which is itself truncated
+  let data = [0u8,0,0];
+  let mut r = Reader::init(&data);
+  let err = PayloadU32::read(&mut r).unwrap_err();
+  assert_eq!(err, DecodeError::Truncated { offset: 0, needed: 4, available: 3 });
+  assert_eq!(err.offset(), 0);
+
+  //errors inside a vector propagate out, labelled with the
+  //element index and the offset within the whole buffer
+  let data = [0u8,3,1,b'a',9];
+  let mut r = Reader::init(&data);
+  let err = read_vec_u16::<PayloadU8>(&mut r).unwrap_err();
+  assert_eq!(err.root(), &DecodeError::Truncated { offset: 5, needed: 9, available: 0 });
+  assert_eq!(err.path(), Some(&super::error::ErrorPath(vec![PathSegment::Index(1)])));
+
+  //custom errors carry the reader's offset
+  let data = [1u8,2,3];
+  let mut r = Reader::init(&data);
+  r.take(2).unwrap();
+  assert_eq!(r.custom("bad version").offset(), 2);
+  assert_eq!(r.invalid("bool"), DecodeError::InvalidValue { offset: 2, what: "bool" });
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_strict_decoding() {
+  let good = [0u8, 2, b'h', b'i'];
+  assert_eq!(PayloadU16::read_exact(&good), Ok(PayloadU16::from_slice(b"hi")));
+  let extra = [0u8, 2, b'h', b'i', 0];
+  assert_eq!(PayloadU16::read_exact(&extra),
+    Err(DecodeError::TrailingData { offset: 4, left: 1 }));
+  let mut r = Reader::init(&extra);
+  r.take(4).unwrap();
+  assert!(r.finish().is_err());
+  r.take(1).unwrap();
+  assert_eq!(r.finish(), Ok(()));
+
+  //a struct inside a length prefix which leaves bytes behind
+  let nested = [0u8, 3, 1, b'x', 0xff];
+  let read_one = |r: &mut Reader| -> Result<(),DecodeError> {
+    let len = r.read_u16()?;
+    let mut sub = r.sub(len)?;
+    PayloadU8::read(&mut sub)?;
+    r.end_sub(sub)
+  };
+  let mut r = Reader::init(&nested);
+  assert!(!r.is_strict());
+  assert_eq!(read_one(&mut r), Ok(()));
+  let mut r = Reader::init(&nested);
+  r.set_strict(true);
+  assert_eq!(read_one(&mut r), Err(DecodeError::TrailingData { offset: 4, left: 1 }));
+  assert!(r.sub(0).unwrap().is_strict());
+}
+#[test]
+fn test_speculative_parsing() {
+  let data = [0x16u8, 0x03, 0x01, 0x00, 0x02, 0xaa, 0xbb];
+  let mut r = Reader::init(&data);
+  assert_eq!(r.peek_u8(), Ok(0x16));
+  assert_eq!(r.peek_u16(), Ok(0x1603));
+  assert_eq!(r.peek(3), Ok(&data[.. 3]));
+  assert_eq!(r.used(), 0);
+  assert_eq!(r.peek(8), Err(DecodeError::Truncated { offset: 0, needed: 8, available: 7 }));
+
+  //a failed layout leaves the reader where it was
+  let v2 = r.try_parse(|r| {
+    r.read_u8()?;
+    let len = r.read_u32()?;
+    r.take(len)
+  });
+  assert!(v2.is_err());
+  assert_eq!(r.used(), 0);
+  let v1 = r.try_parse(|r| {
+    r.take(3)?;
+    r.u16_encoded_slice()
+  });
+  assert_eq!(v1, Ok(&[0xaa, 0xbb][..]));
+  assert!(!r.any_left());
+
+  let mut r = Reader::with_order(&data, ByteOrder::LittleEndian);
+  assert_eq!(r.peek_u16(), Ok(0x0316));
+  let pos = r.checkpoint();
+  r.take(5).unwrap();
+  r.restore(pos);
+  assert_eq!(r.rest(), &data);
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_decode_limits() {
+  //a u16 vector of units claims 0xffff bytes but none are needed
+  let mut data = vec![0xffu8, 0xff];
+  data.extend_from_slice(&[7u8; 0xffff]);
+  let mut r = Reader::init(&data);
+  r.set_limits(DecodeLimits { max_payload_len: 100, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_vec_u16::<u8>(&mut r),
+    Err(DecodeError::LimitExceeded { offset: 2, limit: "max_payload_len" }));
+
+  let mut r = Reader::init(&data);
+  r.set_limits(DecodeLimits { max_elements: 1000, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_vec_u16::<u8>(&mut r),
+    Err(DecodeError::LimitExceeded { offset: 1003, limit: "max_elements" }));
+
+  //the allocation budget covers every vector read from the reader
+  let items = [PayloadU8::from_slice(b"ab"), PayloadU8::from_slice(b"c")];
+  let mut enc = Vec::new();
+  encode_vec_u8(&mut enc, &items);
+  encode_vec_u8(&mut enc, &items);
+  let one = 4 * core::mem::size_of::<PayloadU8>();
+  let mut r = Reader::init(&enc);
+  r.set_limits(DecodeLimits { max_alloc: one, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_vec_u8::<PayloadU8>(&mut r).unwrap(), items.to_vec());
+  assert_eq!(read_vec_u8::<PayloadU8>(&mut r),
+    Err(DecodeError::LimitExceeded { offset: 10, limit: "max_alloc" }));
+  let mut r = Reader::init(&enc);
+  r.set_limits(DecodeLimits { max_alloc: 2 * one, ..DecodeLimits::UNLIMITED });
+  assert!(read_vec_u8::<PayloadU8>(&mut r).is_ok());
+  assert!(read_vec_u8::<PayloadU8>(&mut r).is_ok());
+
+  //nested vectors count towards the depth
+  let nested = [0x01u8, 0x00];
+  let mut r = Reader::init(&nested);
+  r.set_limits(DecodeLimits { max_depth: 1, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_vec_u8::<PayloadU8>(&mut r).unwrap(), vec![PayloadU8::from_slice(b"")]);
+  let nested = [0x02u8, 0x01, 0x00];
+  let mut r = Reader::init(&nested);
+  r.set_limits(DecodeLimits { max_depth: 1, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_vec_u8::<Vec8>(&mut r).unwrap_err().root(),
+    &DecodeError::LimitExceeded { offset: 2, limit: "max_depth" });
+  r = Reader::init(&nested);
+  r.set_limits(DecodeLimits::strict());
+  assert_eq!(read_vec_u8::<Vec8>(&mut r).unwrap().len(), 1);
+  assert_eq!(Vec8::read_exact_with_limits(&nested[1 ..], DecodeLimits::strict()), Ok(Vec8(vec![0])));
+  assert_eq!(PayloadU8::read_exact_with_limits(&nested, DecodeLimits { max_payload_len: 1, ..DecodeLimits::UNLIMITED }),
+    Err(DecodeError::LimitExceeded { offset: 1, limit: "max_payload_len" }));
+
+  //elements which take no bytes cannot fill a non-empty body,
+  //even with no limits set
+  let units = [0u8, 1, 0];
+  let err = read_vec_u16::<()>(&mut Reader::init(&units)).unwrap_err();
+  assert_eq!(err.root(), &DecodeError::InvalidValue { offset: 2, what: "zero length element" });
+  assert!(read_vec_u8::<[u8; 0]>(&mut Reader::init(&[2, 0, 0])).is_err());
+  assert_eq!(read_vec_u16::<()>(&mut Reader::init(&[0, 0])), Ok(vec![]));
+  let mut r = Reader::init(&units);
+  let mut it = r.iter_vec_u16::<()>().unwrap();
+  assert!(it.next().unwrap().is_err());
+  assert!(it.next().is_none());
+  assert!(Reader::init(&units).view_vec_u16::<()>().is_err());
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_encoded_len() {
+  use super::prefix::LengthPrefix;
+
+  //worked out without encoding, and matching what is written
+  let p = PayloadU24::new(vec![0u8; 70]);
+  assert_eq!(p.encoded_len(), 73);
+  let v = PayloadVarint::new(vec![0u8; 200]);
+  assert_eq!(v.encoded_len(), 202);
+  assert_eq!(v.encoded_len(), v.get_encoding().len());
+  assert_eq!(QuicVarint::prefix_len(16383), 2);
+  let t = (1u8, 2u32, [PayloadU8::from_slice(b"ab"), PayloadU8::from_slice(b"")]);
+  assert_eq!(t.encoded_len(), 1 + 4 + 3 + 1);
+  let items = vec![p.clone(), p];
+  assert_eq!(encoded_len_vec_u16(&items), 2 + 146);
+  assert_eq!(encoded_len_vec_u8(&[0u16; 3]), 7);
+
+  //types without their own version fall back to encoding
+  assert_eq!(Vec8(vec![1, 2, 3]).encoded_len(), 4);
+}
+#[cfg(feature = "alloc")]
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "exact_len() gave 1 but encode() wrote 2 bytes")]
+fn test_encoded_len_mismatch() {
+  #[derive(Debug)]
+  struct Wrong;
+  impl<'a> Codec<'a> for Wrong {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+      bytes.extend_from_slice(&[1, 2]);
+    }
+    fn exact_len(&self) -> Option<usize> {
+      Some(1)
+    }
+    fn read(_: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      Ok(Wrong)
+    }
+  }
+  Wrong.get_encoding();
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_encode_once() {
+  use core::sync::atomic::{AtomicUsize, Ordering};
+
+  //a type without `exact_len`, counting calls to `encode`
+  static ENCODES: AtomicUsize = AtomicUsize::new(0);
+  #[derive(Debug)]
+  struct Counted;
+  impl<'a> Codec<'a> for Counted {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+      ENCODES.fetch_add(1, Ordering::Relaxed);
+      bytes.extend_from_slice(&[1, 2, 3]);
+    }
+    fn read(_: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      Ok(Counted)
+    }
+  }
+  fn encodes<F: FnOnce()>(f: F) -> usize {
+    ENCODES.store(0, Ordering::Relaxed);
+    f();
+    ENCODES.load(Ordering::Relaxed)
+  }
+
+  assert_eq!(encodes(|| assert_eq!(Counted.get_encoding(), vec![1, 2, 3])), 1);
+  assert_eq!(encodes(|| assert_eq!(Counted.try_get_encoding(), Ok(vec![1, 2, 3]))), 1);
+
+  //varint prefixes are put in front afterwards, at any depth
+  type Nested = LengthPrefixed<QuicVarint, LengthPrefixed<QuicVarint, LengthPrefixed<QuicVarint, Counted>>>;
+  let nested = Nested::wrap(LengthPrefixed::wrap(LengthPrefixed::wrap(Counted)));
+  assert_eq!(encodes(|| assert_eq!(nested.get_encoding(), vec![5, 4, 3, 1, 2, 3])), 1);
+  assert_eq!(encodes(|| assert_eq!(nested.try_get_encoding(), Ok(vec![5, 4, 3, 1, 2, 3]))), 1);
+  let mut bytes = vec![9];
+  assert_eq!(encodes(|| nested.encode(&mut bytes)), 1);
+  assert_eq!(bytes, vec![9, 5, 4, 3, 1, 2, 3]);
+  let mut bytes = Vec::new();
+  assert_eq!(encodes(|| assert!(LengthPrefixed::<U8, _>::wrap(nested).try_encode(&mut bytes).is_ok())), 1);
+  assert_eq!(bytes, vec![6, 5, 4, 3, 1, 2, 3]);
+
+  #[cfg(feature = "bytes")]
+  {
+    let mut buf = Vec::new();
+    assert_eq!(encodes(|| assert!(Counted.try_encode_buf(&mut buf).is_ok())), 1);
+    let nested = Nested::wrap(LengthPrefixed::wrap(LengthPrefixed::wrap(Counted)));
+    assert_eq!(encodes(|| nested.encode_buf(&mut buf)), 1);
+    assert_eq!(encodes(|| assert!(nested.try_encode_buf(&mut buf).is_ok())), 1);
+    assert_eq!(buf, vec![1, 2, 3, 5, 4, 3, 1, 2, 3, 5, 4, 3, 1, 2, 3]);
+  }
+}
+#[cfg(all(test, feature = "alloc"))]
+#[derive(Debug,PartialEq)]
+struct Vec8(Vec<u8>);
+#[cfg(all(test, feature = "alloc"))]
+impl<'a> Codec<'a> for Vec8 {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    encode_vec_u8(bytes, &self.0);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    read_vec_u8(r).map(Vec8)
+  }
+}
+#[cfg(feature = "alloc")]
+#[test]
+fn test_checked_encoding() {
+
+  //payloads which fit their prefix encode as normal
+  let p = PayloadU8::new(vec![7u8; 255]);
+  assert_eq!(p.try_get_encoding().unwrap(), p.get_encoding());
+
+  //a 300 byte PayloadU8 is rejected and nothing is written
+  let mut bytes = vec![1u8];
+  let p = PayloadU8::new(vec![7u8; 300]);
+  assert_eq!(p.try_encode(&mut bytes),
+    Err(EncodeError::LengthOverflow { max: 0xff, actual: 300 }));
+  assert_eq!(bytes, vec![1u8]);
+  assert!(PayloadU16::new(vec![0u8; 0x10000]).try_get_encoding().is_err());
+  assert!(PayloadU24::new(vec![0u8; 0x10000]).try_get_encoding().is_ok());
+
+  //vectors check the total encoded length
+  let items = vec![PayloadU8::new(vec![0u8; 100]); 3];
+  let mut bytes = vec![1u8];
+  assert_eq!(try_encode_vec_u8(&mut bytes, &items),
+    Err(EncodeError::LengthOverflow { max: 0xff, actual: 303 }));
+  assert_eq!(bytes, vec![1u8]);
+  try_encode_vec_u16(&mut bytes, &items).unwrap();
+  assert_eq!(bytes.len(), 1 + 2 + 303);
+
+  //errors from nested elements unwind the enclosing prefix
+  let items = vec![PayloadU8::new(vec![0u8; 256])];
+  let mut bytes = Vec::new();
+  assert!(try_encode_vec_u32(&mut bytes, &items).is_err());
+  assert!(bytes.is_empty());
 }
 
 
@@ -426,30 +1799,53 @@ pub trait Codec<'a>: Debug + Sized {
  * Encoding U8
  *
  */
+#[cfg(feature = "alloc")]
 pub fn encode_u8(v: u8, bytes: &mut Vec<u8>) {
   bytes.push(v);
 }
 pub fn decode_u8(bytes: &[u8]) -> Option<u8> {
-  Some(bytes[0])
+  bytes.first().copied()
 }
+#[cfg(feature = "alloc")]
 pub fn encode_vec_u8<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
-  let mut sub: Vec<u8> = Vec::new();
-  for i in items {
-    i.encode(&mut sub);
-  }
-  debug_assert!(sub.len() <= 0xff);
-  encode_u8(sub.len() as u8, bytes);
-  bytes.append(&mut sub);
-}
-pub fn read_vec_u8<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Option<Vec<T>> {
-  let len = try_ret!(r.read_u8());
-  let mut ret: Vec<T> = Vec::with_capacity(len);
-  let mut sub = try_ret!(r.sub(len));
+  Writer::init(bytes).u8_prefixed(|w| {
+    for i in items {
+      i.encode(w);
+    }
+  });
+}
+#[cfg(feature = "alloc")]
+pub fn encoded_len_vec_u8<'a,T: Codec<'a>>(items: &[T]) -> usize {
+  1 + items_len(items)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encoded_len_vec_u8<'a,T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  let len = try_items_len(items)?;
+  check_len(len, 0xff)?;
+  Ok(1 + len)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_vec_u8<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  Writer::init(bytes).try_u8_prefixed(|w| {
+    for i in items {
+      i.try_encode(w)?;
+    }
+    Ok(())
+  })
+}
+#[cfg(feature = "alloc")]
+pub fn read_vec_u8<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Result<Vec<T>,DecodeError> {
+  let len = r.read_u8()?;
+  let mut sub = r.sub(len)?;
+  let mut ret: Vec<T> = Vec::new();
   while sub.any_left() {
-    ret.push(try_ret!(T::read(&mut sub)));
+    let item = sub.context_index(ret.len(), Reader::read_element)?;
+    sub.push_limited(&mut ret, item)?;
   }
-  Some(ret)
+  r.end_sub(sub)?;
+  Ok(ret)
 }
+#[cfg(feature = "alloc")]
 #[test]
 fn test_encode_decode_u8() {
   
@@ -496,31 +1892,62 @@ fn test_encode_decode_u8() {
  * Encoding U16
  *
  */
+#[cfg(feature = "alloc")]
 pub fn encode_u16(v: u16, bytes: &mut Vec<u8>) {
   bytes.push((v >> 8) as u8);
   bytes.push(v as u8);
 }
+#[cfg(feature = "alloc")]
+pub fn encode_u16_le(v: u16, bytes: &mut Vec<u8>) {
+  bytes.push(v as u8);
+  bytes.push((v >> 8) as u8);
+}
 pub fn decode_u16(bytes: &[u8]) -> Option<u16> {
+  if bytes.len() < 2 {
+    return None;
+  }
   Some(((bytes[0] as u16) << 8) | bytes[1] as u16)
 }
+#[cfg(feature = "alloc")]
 pub fn encode_vec_u16<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
-  let mut sub: Vec<u8> = Vec::new();
-  for i in items {
-    i.encode(&mut sub);
-  }
-  debug_assert!(sub.len() <= 0xffffff);
-  encode_u16(sub.len() as u16, bytes);
-  bytes.append(&mut sub);
-}
-pub fn read_vec_u16<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Option<Vec<T>> {
-  let len = try_ret!(r.read_u16());
-  let mut ret: Vec<T> = Vec::with_capacity(len);
-  let mut sub = try_ret!(r.sub(len));
+  Writer::init(bytes).u16_prefixed(|w| {
+    for i in items {
+      i.encode(w);
+    }
+  });
+}
+#[cfg(feature = "alloc")]
+pub fn encoded_len_vec_u16<'a,T: Codec<'a>>(items: &[T]) -> usize {
+  2 + items_len(items)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encoded_len_vec_u16<'a,T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  let len = try_items_len(items)?;
+  check_len(len, 0xffff)?;
+  Ok(2 + len)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_vec_u16<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  Writer::init(bytes).try_u16_prefixed(|w| {
+    for i in items {
+      i.try_encode(w)?;
+    }
+    Ok(())
+  })
+}
+#[cfg(feature = "alloc")]
+pub fn read_vec_u16<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Result<Vec<T>,DecodeError> {
+  let len = r.read_u16_be()?;
+  let mut sub = r.sub(len)?;
+  let mut ret: Vec<T> = Vec::new();
   while sub.any_left() {
-    ret.push(try_ret!(T::read(&mut sub)));
+    let item = sub.context_index(ret.len(), Reader::read_element)?;
+    sub.push_limited(&mut ret, item)?;
   }
-  Some(ret)
+  r.end_sub(sub)?;
+  Ok(ret)
 }
+#[cfg(feature = "alloc")]
 #[test]
 fn test_encode_decode_u16() {
     
@@ -561,32 +1988,64 @@ fn test_encode_decode_u16() {
  * Encoding U24
  *
  */
+#[cfg(feature = "alloc")]
 pub fn encode_u24(v: u32, bytes: &mut Vec<u8>) {
   bytes.push((v >> 16) as u8);
   bytes.push((v >> 8) as u8);
   bytes.push(v as u8);
 }
+#[cfg(feature = "alloc")]
+pub fn encode_u24_le(v: u32, bytes: &mut Vec<u8>) {
+  bytes.push(v as u8);
+  bytes.push((v >> 8) as u8);
+  bytes.push((v >> 16) as u8);
+}
 pub fn decode_u24(bytes: &[u8]) -> Option<u32> {
+  if bytes.len() < 3 {
+    return None;
+  }
   Some(((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32)
 }
+#[cfg(feature = "alloc")]
 pub fn encode_vec_u24<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
-  let mut sub: Vec<u8> = Vec::new();
-  for i in items {
-    i.encode(&mut sub);
-  }
-  debug_assert!(sub.len() <= 0x00ffffff);
-  encode_u24(sub.len() as u32, bytes);
-  bytes.append(&mut sub);
-}
-pub fn read_vec_u24<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Option<Vec<T>> {
-  let len = try_ret!(r.read_u24());
-  let mut ret: Vec<T> = Vec::with_capacity(len);
-  let mut sub = try_ret!(r.sub(len));
+  Writer::init(bytes).u24_prefixed(|w| {
+    for i in items {
+      i.encode(w);
+    }
+  });
+}
+#[cfg(feature = "alloc")]
+pub fn encoded_len_vec_u24<'a,T: Codec<'a>>(items: &[T]) -> usize {
+  3 + items_len(items)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encoded_len_vec_u24<'a,T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  let len = try_items_len(items)?;
+  check_len(len, 0x00ff_ffff)?;
+  Ok(3 + len)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_vec_u24<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  Writer::init(bytes).try_u24_prefixed(|w| {
+    for i in items {
+      i.try_encode(w)?;
+    }
+    Ok(())
+  })
+}
+#[cfg(feature = "alloc")]
+pub fn read_vec_u24<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Result<Vec<T>,DecodeError> {
+  let len = r.read_u24_be()?;
+  let mut sub = r.sub(len)?;
+  let mut ret: Vec<T> = Vec::new();
   while sub.any_left() {
-    ret.push(try_ret!(T::read(&mut sub)));
+    let item = sub.context_index(ret.len(), Reader::read_element)?;
+    sub.push_limited(&mut ret, item)?;
   }
-  Some(ret)
+  r.end_sub(sub)?;
+  Ok(ret)
 }
+#[cfg(feature = "alloc")]
 #[test]
 fn test_encode_decode_u24() {
     
@@ -627,13 +2086,24 @@ fn test_encode_decode_u24() {
  * Encoding U32
  *
  */
+#[cfg(feature = "alloc")]
 pub fn encode_u32(v: u32, bytes: &mut Vec<u8>) {
   bytes.push((v >> 24) as u8);
   bytes.push((v >> 16) as u8);
   bytes.push((v >> 8) as u8);
   bytes.push(v as u8);
 }
+#[cfg(feature = "alloc")]
+pub fn encode_u32_le(v: u32, bytes: &mut Vec<u8>) {
+  bytes.push(v as u8);
+  bytes.push((v >> 8) as u8);
+  bytes.push((v >> 16) as u8);
+  bytes.push((v >> 24) as u8);
+}
 pub fn decode_u32(bytes: &[u8]) -> Option<u32> {
+  if bytes.len() < 4 {
+    return None;
+  }
   Some(
        ((bytes[0] as u32) << 24) |
        ((bytes[1] as u32) << 16) |
@@ -641,24 +2111,46 @@ pub fn decode_u32(bytes: &[u8]) -> Option<u32> {
        bytes[3] as u32
       )
 }
+#[cfg(feature = "alloc")]
 pub fn encode_vec_u32<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
-  let mut sub: Vec<u8> = Vec::new();
-  for i in items {
-    i.encode(&mut sub);
-  }
-  debug_assert!(sub.len() <= 0xffffffff);
-  encode_u32(sub.len() as u32, bytes);
-  bytes.append(&mut sub);
-}
-pub fn read_vec_u32<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Option<Vec<T>> {
-  let len = try_ret!(r.read_u32());
-  let mut ret: Vec<T> = Vec::with_capacity(len);
-  let mut sub = try_ret!(r.sub(len));
+  Writer::init(bytes).u32_prefixed(|w| {
+    for i in items {
+      i.encode(w);
+    }
+  });
+}
+#[cfg(feature = "alloc")]
+pub fn encoded_len_vec_u32<'a,T: Codec<'a>>(items: &[T]) -> usize {
+  4 + items_len(items)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encoded_len_vec_u32<'a,T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  let len = try_items_len(items)?;
+  check_len(len, 0xffff_ffff)?;
+  Ok(4 + len)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_vec_u32<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  Writer::init(bytes).try_u32_prefixed(|w| {
+    for i in items {
+      i.try_encode(w)?;
+    }
+    Ok(())
+  })
+}
+#[cfg(feature = "alloc")]
+pub fn read_vec_u32<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Result<Vec<T>,DecodeError> {
+  let len = r.read_u32_be()?;
+  let mut sub = r.sub(len)?;
+  let mut ret: Vec<T> = Vec::new();
   while sub.any_left() {
-    ret.push(try_ret!(T::read(&mut sub)));
+    let item = sub.context_index(ret.len(), Reader::read_element)?;
+    sub.push_limited(&mut ret, item)?;
   }
-  Some(ret)
+  r.end_sub(sub)?;
+  Ok(ret)
 }
+#[cfg(feature = "alloc")]
 #[test]
 fn test_encode_decode_u32() {
     
@@ -699,11 +2191,13 @@ fn test_encode_decode_u32() {
  * Encoding U64
  *
  */
+#[cfg(feature = "alloc")]
 pub fn encode_u64(v: u64, bytes: &mut Vec<u8>) {
   let mut b64 = [0u8; 8];
   put_u64(v, &mut b64);
   bytes.extend_from_slice(&b64);
 }
+#[cfg(feature = "alloc")]
 pub fn put_u64(v: u64, bytes: &mut [u8]) {
   bytes[0] = (v >> 56) as u8;
   bytes[1] = (v >> 48) as u8;
@@ -714,7 +2208,27 @@ pub fn put_u64(v: u64, bytes: &mut [u8]) {
   bytes[6] = (v >> 8) as u8;
   bytes[7] = v as u8;
 }
+#[cfg(feature = "alloc")]
+pub fn encode_u64_le(v: u64, bytes: &mut Vec<u8>) {
+  let mut b64 = [0u8; 8];
+  put_u64_le(v, &mut b64);
+  bytes.extend_from_slice(&b64);
+}
+#[cfg(feature = "alloc")]
+pub fn put_u64_le(v: u64, bytes: &mut [u8]) {
+  bytes[0] = v as u8;
+  bytes[1] = (v >> 8) as u8;
+  bytes[2] = (v >> 16) as u8;
+  bytes[3] = (v >> 24) as u8;
+  bytes[4] = (v >> 32) as u8;
+  bytes[5] = (v >> 40) as u8;
+  bytes[6] = (v >> 48) as u8;
+  bytes[7] = (v >> 56) as u8;
+}
 pub fn decode_u64(bytes: &[u8]) -> Option<u64> {
+  if bytes.len() < 8 {
+    return None;
+  }
   Some(
        ((bytes[0] as u64) << 56) |
        ((bytes[1] as u64) << 48) |
@@ -726,23 +2240,46 @@ pub fn decode_u64(bytes: &[u8]) -> Option<u64> {
        bytes[7] as u64
       )
 }
+#[cfg(feature = "alloc")]
 pub fn encode_vec_u64<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
-  let mut sub: Vec<u8> = Vec::new();
-  for i in items {
-    i.encode(&mut sub);
-  }
-  encode_u64(sub.len() as u64, bytes);
-  bytes.append(&mut sub);
+  Writer::init(bytes).u64_prefixed(|w| {
+    for i in items {
+      i.encode(w);
+    }
+  });
+}
+#[cfg(feature = "alloc")]
+pub fn encoded_len_vec_u64<'a,T: Codec<'a>>(items: &[T]) -> usize {
+  8 + items_len(items)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encoded_len_vec_u64<'a,T: Codec<'a>>(items: &[T]) -> Result<usize,EncodeError> {
+  let len = try_items_len(items)?;
+  check_len(len, u64::MAX)?;
+  Ok(8 + len)
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_vec_u64<'a,T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  Writer::init(bytes).try_u64_prefixed(|w| {
+    for i in items {
+      i.try_encode(w)?;
+    }
+    Ok(())
+  })
 }
-pub fn read_vec_u64<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Option<Vec<T>> {
-  let len = try_ret!(r.read_u64());
-  let mut ret: Vec<T> = Vec::with_capacity(len);
-  let mut sub = try_ret!(r.sub(len));
+#[cfg(feature = "alloc")]
+pub fn read_vec_u64<'a,T: Codec<'a>>(r: &mut Reader<'a>)-> Result<Vec<T>,DecodeError> {
+  let len = r.read_u64_be()?;
+  let mut sub = r.sub(len)?;
+  let mut ret: Vec<T> = Vec::new();
   while sub.any_left() {
-    ret.push(try_ret!(T::read(&mut sub)));
+    let item = sub.context_index(ret.len(), Reader::read_element)?;
+    sub.push_limited(&mut ret, item)?;
   }
-  Some(ret)
+  r.end_sub(sub)?;
+  Ok(ret)
 }
+#[cfg(feature = "alloc")]
 #[test]
 fn test_encode_decode_u64() {
     
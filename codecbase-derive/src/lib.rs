@@ -0,0 +1,572 @@
+//! `#[derive(Codec)]` for codecbase.
+//!
+//! Structs encode their fields in declaration order. A `Vec<T>` field
+//! must declare the width of its byte length prefix:
+//!
+//! ```ignore
+//! #[derive(Debug,Codec)]
+//! struct Hello<'a> {
+//!   random: PayloadU8<'a>,
+//!   #[codec(prefix = "u16")]
+//!   suites: Vec<PayloadU8<'a>>,
+//! }
+//! ```
+//!
+//! Enums write a tag before the variant's fields. The tag width is set
+//! with `#[codec(tag = "u8")]` on the enum, each variant's tag value is
+//! taken from `#[codec(value = N)]`, an integer discriminant, or its
+//! position, in that order.
+//!
+//! The first lifetime parameter of the type is the one borrowed from
+//! the `Reader`, so borrowed payload fields stay zero-copy.
+//!
+//! Decode errors are labelled with the field, and for enums the
+//! variant, they came from using `Reader::context`.
+//!
+//! `#[derive(IntoOwned)]` converts every field with
+//! `IntoOwned::into_owned`. The owned type is the same type with each
+//! lifetime replaced by `'static` and each type parameter `T` by
+//! `T::Owned`, so `Hello<'a>` becomes `Hello<'static>`.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as Tokens};
+use quote::{format_ident, quote};
+use syn::{
+  parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Expr, ExprLit, Fields,
+  GenericParam, Lifetime, LifetimeParam, Lit, LitInt, LitStr, Member, Result,
+};
+
+#[proc_macro_derive(Codec, attributes(codec))]
+pub fn derive_codec(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match expand(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into(),
+  }
+}
+
+#[proc_macro_derive(IntoOwned)]
+pub fn derive_into_owned(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match expand_into_owned(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into(),
+  }
+}
+
+///Width of a length prefix or enum tag
+#[derive(Clone,Copy)]
+enum Width {
+  U8,
+  U16,
+  U24,
+  U32,
+  U64,
+}
+
+impl Width {
+  fn parse(lit: &LitStr) -> Result<Width> {
+    match lit.value().as_str() {
+      "u8" => Ok(Width::U8),
+      "u16" => Ok(Width::U16),
+      "u24" => Ok(Width::U24),
+      "u32" => Ok(Width::U32),
+      "u64" => Ok(Width::U64),
+      _ => Err(Error::new(lit.span(), "expected one of \"u8\", \"u16\", \"u24\", \"u32\", \"u64\"")),
+    }
+  }
+
+  fn name(self) -> &'static str {
+    match self {
+      Width::U8 => "u8",
+      Width::U16 => "u16",
+      Width::U24 => "u24",
+      Width::U32 => "u32",
+      Width::U64 => "u64",
+    }
+  }
+
+  ///largest value which fits
+  fn max(self) -> u64 {
+    match self {
+      Width::U8 => 0xff,
+      Width::U16 => 0xffff,
+      Width::U24 => 0x00ff_ffff,
+      Width::U32 => 0xffff_ffff,
+      Width::U64 => u64::MAX,
+    }
+  }
+
+  ///number of bytes written
+  fn size(self) -> usize {
+    match self {
+      Width::U8 => 1,
+      Width::U16 => 2,
+      Width::U24 => 3,
+      Width::U32 => 4,
+      Width::U64 => 8,
+    }
+  }
+
+  ///the integer type `encode_*` takes for this width
+  fn int_type(self) -> Tokens {
+    match self {
+      Width::U8 => quote!(u8),
+      Width::U16 => quote!(u16),
+      Width::U24 | Width::U32 => quote!(u32),
+      Width::U64 => quote!(u64),
+    }
+  }
+}
+
+///Settings read from `#[codec(...)]` attributes
+#[derive(Default)]
+struct Attrs {
+  prefix: Option<Width>,
+  tag: Option<Width>,
+  value: Option<LitInt>,
+}
+
+fn parse_attrs(attrs: &[Attribute]) -> Result<Attrs> {
+  let mut out = Attrs::default();
+  for attr in attrs {
+    if !attr.path().is_ident("codec") {
+      continue;
+    }
+    attr.parse_nested_meta(|meta| {
+      if meta.path.is_ident("prefix") {
+        out.prefix = Some(Width::parse(&meta.value()?.parse()?)?);
+      } else if meta.path.is_ident("tag") {
+        out.tag = Some(Width::parse(&meta.value()?.parse()?)?);
+      } else if meta.path.is_ident("value") {
+        out.value = Some(meta.value()?.parse()?);
+      } else {
+        return Err(meta.error("unknown codec attribute"));
+      }
+      Ok(())
+    })?;
+  }
+  Ok(out)
+}
+
+///One field to be encoded, `access` evaluates to a reference to it
+struct FieldCode {
+  member: Member,
+  access: Tokens,
+  ty: syn::Type,
+  prefix: Option<Width>,
+}
+
+fn collect_fields<F>(fields: &Fields, access: F) -> Result<Vec<FieldCode>>
+where F: Fn(usize, &Member) -> Tokens
+{
+  let mut out = Vec::new();
+  for (i, field) in fields.iter().enumerate() {
+    let member = match field.ident {
+      Some(ref ident) => Member::Named(ident.clone()),
+      None => Member::Unnamed(i.into()),
+    };
+    let attrs = parse_attrs(&field.attrs)?;
+    if attrs.tag.is_some() || attrs.value.is_some() {
+      return Err(Error::new_spanned(field, "only `prefix` is allowed on fields"));
+    }
+    out.push(FieldCode {
+      access: access(i, &member),
+      member,
+      ty: field.ty.clone(),
+      prefix: attrs.prefix,
+    });
+  }
+  Ok(out)
+}
+
+fn encode_fields(fields: &[FieldCode]) -> Tokens {
+  let stmts = fields.iter().map(|f| {
+    let access = &f.access;
+    match f.prefix {
+      Some(w) => {
+        let func = format_ident!("encode_vec_{}", w.name());
+        quote!(::codecbase::#func(bytes, #access);)
+      }
+      None => quote!(::codecbase::Codec::encode(#access, bytes);),
+    }
+  });
+  quote!(#(#stmts)*)
+}
+
+fn try_encode_fields(fields: &[FieldCode]) -> Tokens {
+  let stmts = fields.iter().map(|f| {
+    let access = &f.access;
+    match f.prefix {
+      Some(w) => {
+        let func = format_ident!("try_encode_vec_{}", w.name());
+        quote!(::codecbase::#func(bytes, #access)?;)
+      }
+      None => quote!(::codecbase::Codec::try_encode(#access, bytes)?;),
+    }
+  });
+  quote!(#(#stmts)*)
+}
+
+///Sum of the exact lengths of every field, returning early with
+///`None` if one is not known without encoding
+fn exact_len_fields(fields: &[FieldCode]) -> Tokens {
+  let terms = fields.iter().map(|f| {
+    let access = &f.access;
+    match f.prefix {
+      Some(w) => {
+        let width = w.size();
+        quote!(+ #width + ::codecbase::__private::items_exact_len(#access)?)
+      }
+      None => quote!(+ ::codecbase::Codec::exact_len(#access)?),
+    }
+  });
+  quote!(0 #(#terms)*)
+}
+
+///Sum of the checked encoded lengths of every field, returning
+///early with the first error
+fn try_encoded_len_fields(fields: &[FieldCode]) -> Tokens {
+  let terms = fields.iter().map(|f| {
+    let access = &f.access;
+    match f.prefix {
+      Some(w) => {
+        let func = format_ident!("try_encoded_len_vec_{}", w.name());
+        quote!(+ ::codecbase::#func(#access)?)
+      }
+      None => quote!(+ ::codecbase::Codec::try_encoded_len(#access)?),
+    }
+  });
+  quote!(0 #(#terms)*)
+}
+
+fn encode_buf_fields(fields: &[FieldCode]) -> Tokens {
+  let stmts = fields.iter().map(|f| {
+    let access = &f.access;
+    match f.prefix {
+      Some(w) => {
+        let func = format_ident!("encode_vec_{}_buf", w.name());
+        quote!(::codecbase::#func(buf, #access);)
+      }
+      None => quote!(::codecbase::Codec::encode_buf(#access, buf);),
+    }
+  });
+  quote!(#(#stmts)*)
+}
+
+///Build `Path { member: read, .. }` which reads every field in order,
+///errors are labelled with the field name
+fn read_fields(path: Tokens, fields: &[FieldCode], lt: &Lifetime) -> Tokens {
+  let inits = fields.iter().map(|f| {
+    let member = &f.member;
+    let ty = &f.ty;
+    let name = match *member {
+      Member::Named(ref ident) => ident.to_string(),
+      Member::Unnamed(ref index) => index.index.to_string(),
+    };
+    let read = match f.prefix {
+      Some(w) => {
+        let func = format_ident!("read_vec_{}", w.name());
+        quote!(::codecbase::#func)
+      }
+      None => quote!(<#ty as ::codecbase::Codec<#lt>>::read),
+    };
+    quote!(#member: r.context(#name, #read)?)
+  });
+  quote!(#path { #(#inits,)* })
+}
+
+///Build `Path { member: into_owned(__field{n}), .. }` from the bound
+///fields of a variant
+fn own_fields(path: Tokens, fields: &[FieldCode]) -> Tokens {
+  let inits = fields.iter().map(|f| {
+    let member = &f.member;
+    let access = &f.access;
+    quote!(#member: ::codecbase::IntoOwned::into_owned(#access))
+  });
+  quote!(#path { #(#inits,)* })
+}
+
+///Pattern binding every field of a variant to `__field{n}`
+fn bind_fields(path: Tokens, fields: &[FieldCode]) -> Tokens {
+  let binds = fields.iter().map(|f| {
+    let member = &f.member;
+    let access = &f.access;
+    quote!(#member: #access)
+  });
+  quote!(#path { #(#binds,)* })
+}
+
+fn variant_value(attrs: &Attrs, discriminant: Option<&Expr>, index: usize) -> Result<u64> {
+  if let Some(ref lit) = attrs.value {
+    return lit.base10_parse();
+  }
+  match discriminant {
+    Some(Expr::Lit(ExprLit { lit: Lit::Int(ref lit), .. })) => lit.base10_parse(),
+    Some(expr) => Err(Error::new_spanned(expr, "use #[codec(value = N)] for non-literal discriminants")),
+    None => Ok(index as u64),
+  }
+}
+
+fn expand(input: &DeriveInput) -> Result<Tokens> {
+  let name = &input.ident;
+
+  //the reader lifetime is the type's first lifetime, or a fresh one
+  let mut impl_generics = input.generics.clone();
+  let lt = match input.generics.lifetimes().next() {
+    Some(param) => param.lifetime.clone(),
+    None => {
+      let lt = Lifetime::new("'__codec", Span::call_site());
+      impl_generics.params.insert(0, GenericParam::Lifetime(LifetimeParam::new(lt.clone())));
+      lt
+    }
+  };
+  let type_params: Vec<_> = input.generics.type_params().map(|p| p.ident.clone()).collect();
+  {
+    let where_clause = impl_generics.make_where_clause();
+    for ident in type_params {
+      where_clause.predicates.push(parse_quote!(#ident: ::codecbase::Codec<#lt>));
+    }
+  }
+  let (impl_generics, _, where_clause) = impl_generics.split_for_impl();
+  let (_, ty_generics, _) = input.generics.split_for_impl();
+
+  let attrs = parse_attrs(&input.attrs)?;
+  if attrs.prefix.is_some() || attrs.value.is_some() {
+    return Err(Error::new_spanned(name, "only `tag` is allowed on the type"));
+  }
+
+  let (encode, try_encode, exact_len, try_encoded_len, encode_buf, read) = match input.data {
+    Data::Struct(ref data) => {
+      if attrs.tag.is_some() {
+        return Err(Error::new_spanned(name, "`tag` is only allowed on enums"));
+      }
+      let fields = collect_fields(&data.fields, |_, member| quote!(&self.#member))?;
+      (
+        encode_fields(&fields),
+        try_encode_fields(&fields),
+        {
+          let len = exact_len_fields(&fields);
+          quote!(::core::option::Option::Some(#len))
+        },
+        try_encoded_len_fields(&fields),
+        encode_buf_fields(&fields),
+        {
+          let build = read_fields(quote!(#name), &fields, &lt);
+          quote!(Ok(#build))
+        },
+      )
+    }
+    Data::Enum(ref data) => {
+      let tag = match attrs.tag {
+        Some(tag) => tag,
+        None => return Err(Error::new_spanned(name, "enums need #[codec(tag = \"u8\")] or similar")),
+      };
+      //tags are big endian whatever the reader's byte order
+      let read_tag = match tag {
+        Width::U8 => format_ident!("read_u8"),
+        _ => format_ident!("read_{}_be", tag.name()),
+      };
+      let encode_tag = format_ident!("encode_{}", tag.name());
+      let int = tag.int_type();
+      let mut encode_arms = Vec::new();
+      let mut try_encode_arms = Vec::new();
+      let mut exact_len_arms = Vec::new();
+      let mut try_encoded_len_arms = Vec::new();
+      let mut encode_buf_arms = Vec::new();
+      let mut read_arms = Vec::new();
+      let mut seen: Vec<(u64, &syn::Ident)> = Vec::new();
+      for (index, variant) in data.variants.iter().enumerate() {
+        let vname = &variant.ident;
+        let vattrs = parse_attrs(&variant.attrs)?;
+        if vattrs.prefix.is_some() || vattrs.tag.is_some() {
+          return Err(Error::new_spanned(variant, "only `value` is allowed on variants"));
+        }
+        let value = variant_value(&vattrs, variant.discriminant.as_ref().map(|d| &d.1), index)?;
+        if value > tag.max() {
+          return Err(Error::new_spanned(variant,
+            format!("tag value {} does not fit in a {} tag", value, tag.name())));
+        }
+        if let Some(&(_, other)) = seen.iter().find(|&&(v, _)| v == value) {
+          return Err(Error::new_spanned(variant,
+            format!("tag value {} is already used by `{}`", value, other)));
+        }
+        seen.push((value, vname));
+        let fields = collect_fields(&variant.fields, |i, _| {
+          let bind = format_ident!("__field{}", i);
+          quote!(#bind)
+        })?;
+        let pattern = bind_fields(quote!(#name::#vname), &fields);
+        let encode = encode_fields(&fields);
+        let try_encode = try_encode_fields(&fields);
+        let exact_len = exact_len_fields(&fields);
+        let try_encoded_len = try_encoded_len_fields(&fields);
+        let encode_buf = encode_buf_fields(&fields);
+        let build = read_fields(quote!(#name::#vname), &fields, &lt);
+        encode_arms.push(quote!(#pattern => {
+          ::codecbase::#encode_tag(#value as #int, bytes);
+          #encode
+        }));
+        try_encode_arms.push(quote!(#pattern => {
+          ::codecbase::#encode_tag(#value as #int, bytes);
+          #try_encode
+        }));
+        let tag_len = tag.size();
+        exact_len_arms.push(quote!(#pattern => ::core::option::Option::Some(#tag_len + #exact_len),));
+        try_encoded_len_arms.push(quote!(#pattern => #tag_len + #try_encoded_len,));
+        encode_buf_arms.push(quote!(#pattern => {
+          ::codecbase::__private::BufMut::put_uint(buf, #value, #tag_len);
+          #encode_buf
+        }));
+        let vname_str = vname.to_string();
+        read_arms.push(quote!(#value => r.context(#vname_str, |r| Ok(#build)),));
+      }
+      (
+        quote!(match self { #(#encode_arms)* }),
+        quote!(match self { #(#try_encode_arms)* }),
+        quote!(match self { #(#exact_len_arms)* }),
+        quote!(match self { #(#try_encoded_len_arms)* }),
+        quote!(match self { #(#encode_buf_arms)* }),
+        quote!(
+          let unknown = r.invalid("enum tag");
+          match r.#read_tag()? as u64 {
+            #(#read_arms)*
+            _ => Err(unknown),
+          }
+        ),
+      )
+    }
+    Data::Union(_) => return Err(Error::new_spanned(name, "unions cannot derive Codec")),
+  };
+
+  Ok(quote! {
+    impl #impl_generics ::codecbase::Codec<#lt> for #name #ty_generics #where_clause {
+      #[allow(unused_variables)]
+      fn encode(&self, bytes: &mut ::codecbase::__private::Vec<u8>) {
+        #encode
+      }
+      #[allow(unused_variables)]
+      fn try_encode(&self, bytes: &mut ::codecbase::__private::Vec<u8>)
+        -> ::core::result::Result<(), ::codecbase::EncodeError>
+      {
+        #try_encode
+        Ok(())
+      }
+      #[allow(unused_variables)]
+      fn exact_len(&self) -> ::core::option::Option<usize> {
+        #exact_len
+      }
+      #[allow(unused_variables)]
+      fn try_encoded_len(&self) -> ::core::result::Result<usize, ::codecbase::EncodeError> {
+        Ok(#try_encoded_len)
+      }
+      ::codecbase::__if_bytes! {
+        #[allow(unused_variables)]
+        fn encode_buf<__CodecBuf: ::codecbase::__private::BufMut>(&self, buf: &mut __CodecBuf) {
+          #encode_buf
+        }
+      }
+      #[allow(unused_variables)]
+      fn read(r: &mut ::codecbase::Reader<#lt>)
+        -> ::core::result::Result<Self, ::codecbase::DecodeError>
+      {
+        #read
+      }
+    }
+  })
+}
+
+fn expand_into_owned(input: &DeriveInput) -> Result<Tokens> {
+  let name = &input.ident;
+
+  //lifetimes become 'static and type parameters their owned form
+  let mut impl_generics = input.generics.clone();
+  let owned_args: Vec<_> = input.generics.params.iter().map(|param| match *param {
+    GenericParam::Lifetime(_) => quote!('static),
+    GenericParam::Type(ref p) => {
+      let ident = &p.ident;
+      quote!(<#ident as ::codecbase::IntoOwned>::Owned)
+    }
+    GenericParam::Const(ref p) => {
+      let ident = &p.ident;
+      quote!(#ident)
+    }
+  }).collect();
+  let type_params: Vec<_> = input.generics.type_params().map(|p| p.ident.clone()).collect();
+  {
+    let where_clause = impl_generics.make_where_clause();
+    for ident in type_params {
+      where_clause.predicates.push(parse_quote!(#ident: ::codecbase::IntoOwned));
+    }
+  }
+  let (impl_generics, ty_generics, where_clause) = impl_generics.split_for_impl();
+  let owned = if owned_args.is_empty() {
+    quote!(#name)
+  } else {
+    quote!(#name<#(#owned_args),*>)
+  };
+
+  let bind = |i: usize, _: &Member| {
+    let bind = format_ident!("__field{}", i);
+    quote!(#bind)
+  };
+  let body = match input.data {
+    Data::Struct(ref data) => {
+      let fields = collect_fields(&data.fields, bind)?;
+      let pattern = bind_fields(quote!(#name), &fields);
+      let build = own_fields(quote!(#name), &fields);
+      quote!(let #pattern = self; #build)
+    }
+    Data::Enum(ref data) => {
+      let mut arms = Vec::new();
+      for variant in data.variants.iter() {
+        let vname = &variant.ident;
+        let fields = collect_fields(&variant.fields, bind)?;
+        let pattern = bind_fields(quote!(#name::#vname), &fields);
+        let build = own_fields(quote!(#name::#vname), &fields);
+        arms.push(quote!(#pattern => #build,));
+      }
+      quote!(match self { #(#arms)* })
+    }
+    Data::Union(_) => return Err(Error::new_spanned(name, "unions cannot derive IntoOwned")),
+  };
+
+  Ok(quote! {
+    impl #impl_generics ::codecbase::IntoOwned for #name #ty_generics #where_clause {
+      type Owned = #owned;
+      fn into_owned(self) -> Self::Owned {
+        #body
+      }
+    }
+  })
+}
+
+#[test]
+fn test_tag_values() {
+  let check = |input: DeriveInput| expand(&input).map(|_| ()).map_err(|e| e.to_string());
+
+  assert!(check(parse_quote! {
+    #[codec(tag = "u8")]
+    enum E { #[codec(value = 0)] A, #[codec(value = 255)] B }
+  }).is_ok());
+  assert_eq!(check(parse_quote! {
+    #[codec(tag = "u8")]
+    enum E { A, #[codec(value = 300)] B }
+  }), Err("tag value 300 does not fit in a u8 tag".to_string()));
+  assert!(check(parse_quote! {
+    #[codec(tag = "u24")]
+    enum E { #[codec(value = 0xffffff)] A }
+  }).is_ok());
+  assert!(check(parse_quote! {
+    #[codec(tag = "u24")]
+    enum E { #[codec(value = 0x1000000)] A }
+  }).is_err());
+
+  //a repeated value would make the later variant unreadable
+  assert_eq!(check(parse_quote! {
+    #[codec(tag = "u16")]
+    enum E { A = 1, B = 2, #[codec(value = 1)] C }
+  }), Err("tag value 1 is already used by `A`".to_string()));
+}
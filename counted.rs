@@ -0,0 +1,252 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref,DerefMut};
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+
+use super::codec::{Codec, Reader, items_exact_len, try_items_len};
+use super::error::{DecodeError, EncodeError};
+use super::owned::IntoOwned;
+use super::prefix::{LengthPrefix, U8, U16, U24, U32, U64};
+#[cfg(feature = "bytes")]
+use super::prefix::put_prefix;
+
+///Read `L` giving the number of elements, then that many `T`.
+///
+///The count is checked before anything is decoded. It must be
+///within `max_elements`, and the reader must hold at least
+///`FIXED_LEN` bytes for each element, or one byte when that is
+///unknown, so a huge count cannot be paired with tiny elements.
+///Elements which never take any bytes are bounded by
+///`max_elements` alone. The elements count towards `max_depth`
+///as those of the byte length vectors do.
+fn read_counted<'a, L: LengthPrefix, T: Codec<'a>>(r: &mut Reader<'a>) -> Result<Vec<T>,DecodeError> {
+  let count = L::read_len(r)?;
+  if count > r.limits().max_elements {
+    return Err(r.limit_exceeded("max_elements"));
+  }
+  let needed = count.saturating_mul(T::FIXED_LEN.unwrap_or(1));
+  if needed > r.left() {
+    return Err(r.truncated(needed));
+  }
+  r.nested(|r| {
+    let mut ret: Vec<T> = Vec::new();
+    while ret.len() < count {
+      let item = r.context_index(ret.len(), T::read)?;
+      r.push_limited(&mut ret, item)?;
+    }
+    Ok(ret)
+  })
+}
+
+fn encode_counted<'a, L: LengthPrefix, T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
+  L::encode_len(items.len(), bytes);
+  for i in items {
+    i.encode(bytes);
+  }
+}
+
+fn check_count<L: LengthPrefix>(count: usize) -> Result<(),EncodeError> {
+  if (count as u64) > L::MAX {
+    return Err(EncodeError::ValueOverflow { max: L::MAX, actual: count as u64 });
+  }
+  Ok(())
+}
+
+fn try_encode_counted<'a, L: LengthPrefix, T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+  check_count::<L>(items.len())?;
+  let start = bytes.len();
+  L::encode_len(items.len(), bytes);
+  let ret = items.iter().try_for_each(|i| i.try_encode(bytes));
+  if ret.is_err() {
+    bytes.truncate(start);
+  }
+  ret
+}
+
+///A vector prefixed by its number of elements rather than its
+///length in bytes, as in XDR or Kafka arrays. `L` gives the width
+///of the count.
+pub struct CountedVec<L, T>(pub Vec<T>, PhantomData<fn() -> L>);
+
+///Vector with a u8 element count
+pub type CountedVecU8<T> = CountedVec<U8, T>;
+///Vector with a big endian u16 element count
+pub type CountedVecU16<T> = CountedVec<U16, T>;
+///Vector with a big endian u24 element count
+pub type CountedVecU24<T> = CountedVec<U24, T>;
+///Vector with a big endian u32 element count
+pub type CountedVecU32<T> = CountedVec<U32, T>;
+///Vector with a big endian u64 element count
+pub type CountedVecU64<T> = CountedVec<U64, T>;
+
+impl<L, T> CountedVec<L, T> {
+  pub fn new(items: Vec<T>) -> Self {
+    CountedVec(items, PhantomData)
+  }
+
+  pub fn into_inner(self) -> Vec<T> {
+    self.0
+  }
+}
+
+impl<L, T> Deref for CountedVec<L, T> {
+  type Target = Vec<T>;
+  fn deref(&self) -> &Vec<T> {
+    &self.0
+  }
+}
+
+impl<L, T> DerefMut for CountedVec<L, T> {
+  fn deref_mut(&mut self) -> &mut Vec<T> {
+    &mut self.0
+  }
+}
+
+impl<L, T> From<Vec<T>> for CountedVec<L, T> {
+  fn from(x: Vec<T>) -> Self {
+    CountedVec::new(x)
+  }
+}
+
+impl<L, T: Clone> Clone for CountedVec<L, T> {
+  fn clone(&self) -> Self {
+    CountedVec::new(self.0.clone())
+  }
+}
+
+impl<L, T: PartialEq> PartialEq for CountedVec<L, T> {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+impl<L, T: Eq> Eq for CountedVec<L, T> { }
+
+impl<L, T: fmt::Debug> fmt::Debug for CountedVec<L, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_tuple("CountedVec").field(&self.0).finish()
+  }
+}
+
+impl<'a, L: LengthPrefix, T: Codec<'a>> Codec<'a> for CountedVec<L, T> {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    encode_counted::<L, T>(bytes, &self.0);
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    try_encode_counted::<L, T>(bytes, &self.0)
+  }
+  fn exact_len(&self) -> Option<usize> {
+    Some(L::prefix_len(self.0.len()) + items_exact_len(&self.0)?)
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    check_count::<L>(self.0.len())?;
+    Ok(L::prefix_len(self.0.len()) + try_items_len(&self.0)?)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    put_prefix::<L, _>(self.0.len(), buf);
+    for i in self.0.iter() {
+      i.encode_buf(buf);
+    }
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    read_counted::<L, T>(r).map(CountedVec::new)
+  }
+}
+
+impl<L: 'static, T: IntoOwned> IntoOwned for CountedVec<L, T> {
+  type Owned = CountedVec<L, T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    CountedVec::new(self.0.into_owned())
+  }
+}
+
+macro_rules! countedvec {
+  ($encode: ident, $try_encode: ident, $read: ident, $prefix: ident) => (
+    ///Encode `items` preceded by their number
+    pub fn $encode<'a, T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) {
+      encode_counted::<$prefix, T>(bytes, items);
+    }
+    ///Checked version, nothing is written on error
+    pub fn $try_encode<'a, T: Codec<'a>>(bytes: &mut Vec<u8>, items: &[T]) -> Result<(),EncodeError> {
+      try_encode_counted::<$prefix, T>(bytes, items)
+    }
+    ///Read an element count followed by that many `T`
+    pub fn $read<'a, T: Codec<'a>>(r: &mut Reader<'a>) -> Result<Vec<T>,DecodeError> {
+      read_counted::<$prefix, T>(r)
+    }
+  )
+}
+countedvec!(encode_counted_vec_u8, try_encode_counted_vec_u8, read_counted_vec_u8, U8);
+countedvec!(encode_counted_vec_u16, try_encode_counted_vec_u16, read_counted_vec_u16, U16);
+countedvec!(encode_counted_vec_u24, try_encode_counted_vec_u24, read_counted_vec_u24, U24);
+countedvec!(encode_counted_vec_u32, try_encode_counted_vec_u32, read_counted_vec_u32, U32);
+countedvec!(encode_counted_vec_u64, try_encode_counted_vec_u64, read_counted_vec_u64, U64);
+
+#[test]
+fn test_counted_vec() {
+  use super::codec::{DecodeLimits, PayloadU8};
+
+  let items = [PayloadU8::from_slice(b"ab"), PayloadU8::from_slice(b"")];
+  let mut bytes = Vec::new();
+  encode_counted_vec_u32(&mut bytes, &items);
+  assert_eq!(bytes, vec![0,0,0,2, 2,b'a',b'b', 0]);
+  let mut r = Reader::init(&bytes);
+  assert_eq!(read_counted_vec_u32::<PayloadU8>(&mut r), Ok(items.to_vec()));
+  assert!(!r.any_left());
+
+  let v = CountedVecU16::new(vec![0x0102u16, 0x0304]);
+  assert_eq!(v.get_encoding(), vec![0,2, 1,2, 3,4]);
+  assert_eq!(CountedVecU16::<u16>::read_exact(&[0,2, 1,2, 3,4]), Ok(v));
+  assert_eq!(CountedVecU8::<u16>::read_exact(&[0]), Ok(CountedVec::new(vec![])));
+
+  //a huge count is refused before anything is decoded
+  let huge = [0xff,0xff,0xff,0xff, 0,1];
+  assert_eq!(read_counted_vec_u32::<u16>(&mut Reader::init(&huge)),
+    Err(DecodeError::Truncated { offset: 4, needed: 0x1_ffff_fffe, available: 2 }));
+  assert_eq!(read_counted_vec_u32::<PayloadU8>(&mut Reader::init(&huge)),
+    Err(DecodeError::Truncated { offset: 4, needed: 0xffff_ffff, available: 2 }));
+  let mut r = Reader::init(&huge);
+  r.set_limits(DecodeLimits::strict());
+  assert_eq!(read_counted_vec_u32::<()>(&mut r),
+    Err(DecodeError::LimitExceeded { offset: 4, limit: "max_elements" }));
+  let mut r = Reader::init(&[0,0,0,3, 0,0,0]);
+  r.set_limits(DecodeLimits { max_elements: 2, ..DecodeLimits::UNLIMITED });
+  assert_eq!(read_counted_vec_u32::<u8>(&mut r),
+    Err(DecodeError::LimitExceeded { offset: 4, limit: "max_elements" }));
+
+  //a count which does not fit the prefix writes nothing
+  let many = vec![0u8; 256];
+  let mut bytes = vec![0xaa];
+  assert_eq!(try_encode_counted_vec_u8(&mut bytes, &many),
+    Err(EncodeError::ValueOverflow { max: 0xff, actual: 256 }));
+  assert_eq!(bytes, vec![0xaa]);
+  try_encode_counted_vec_u16(&mut bytes, &many).unwrap();
+  assert_eq!(&bytes[.. 3], &[0xaa, 1, 0]);
+
+  //elements which take no bytes need none on the wire
+  let units = CountedVecU8::new(vec![(), ()]);
+  assert_eq!(units.get_encoding(), vec![2]);
+  assert_eq!(CountedVecU8::<()>::read_exact(&[2]), Ok(units));
+
+  //nesting counts towards max_depth, rather than overflowing the stack
+  #[derive(Debug)]
+  struct Node(CountedVecU8<Node>);
+  impl<'a> Codec<'a> for Node {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+      self.0.encode(bytes);
+    }
+    fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      CountedVecU8::read(r).map(Node)
+    }
+  }
+  let deep = vec![1u8; 200_000];
+  let err = Node::read_exact_with_limits(&deep, DecodeLimits::strict()).unwrap_err();
+  assert_eq!(err.root(), &DecodeError::LimitExceeded { offset: 17, limit: "max_depth" });
+  let mut shallow = vec![1u8; 15];
+  shallow.push(0);
+  assert!(Node::read_exact_with_limits(&shallow, DecodeLimits::strict()).is_ok());
+}
@@ -0,0 +1,350 @@
+#[cfg(not(feature = "std"))]
+use core::cell::{RefCell, RefMut};
+use core::fmt::{self, Write};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::codec::{Codec, Event, Reader};
+use super::error::{DecodeError, PathSegment};
+
+///Record of every read made through a traced `Reader` and the
+///`sub` readers made from it, used to build an annotated dump.
+///
+///With `std` the events are kept behind a `Mutex`, so `Trace` is
+///`Sync` and readers stay `Send` and `Sync`. Without it there is
+///no lock to use, and they are kept in a `RefCell`.
+#[derive(Debug,Default)]
+pub struct Trace {
+  #[cfg(feature = "std")]
+  events: Mutex<Vec<Event>>,
+  #[cfg(not(feature = "std"))]
+  events: RefCell<Vec<Event>>
+}
+
+impl Trace {
+
+  pub fn new() -> Trace {
+    Trace::default()
+  }
+
+  #[cfg(feature = "std")]
+  fn events(&self) -> MutexGuard<'_, Vec<Event>> {
+    //a panic while tracing leaves nothing half written
+    self.events.lock().unwrap_or_else(|e| e.into_inner())
+  }
+
+  #[cfg(not(feature = "std"))]
+  fn events(&self) -> RefMut<'_, Vec<Event>> {
+    self.events.borrow_mut()
+  }
+
+  pub(crate) fn push(&self, event: Event) {
+    self.events().push(event);
+  }
+
+  pub(crate) fn len(&self) -> usize {
+    self.events().len()
+  }
+
+  ///forget events after the first `len`, on `Reader::restore`
+  pub(crate) fn truncate(&self, len: usize) {
+    self.events().truncate(len);
+  }
+
+  ///Arrange the recorded reads into a tree. Field and element
+  ///scopes from `Reader::context` and the ranges of `sub` readers
+  ///hold the reads made within them.
+  pub fn nodes(&self) -> Vec<Node> {
+    let mut roots = Vec::new();
+    //open scopes, with the end offset of those made by `sub`
+    let mut stack: Vec<(Node, Option<usize>)> = Vec::new();
+    for event in self.events().iter() {
+      let offset = match *event {
+        Event::Int { offset, .. } |
+        Event::Bytes { offset, .. } |
+        Event::Sub { offset, .. } |
+        Event::Enter { offset, .. } |
+        Event::Exit { offset } => offset
+      };
+      //a read past the end of a sub reader is back in its parent
+      if let Event::Exit { .. } = *event {} else {
+        while let Some(&(_, Some(end))) = stack.last() {
+          if offset < end {
+            break;
+          }
+          close(&mut stack, &mut roots, end);
+        }
+      }
+      match *event {
+        Event::Int { width, value, .. } => {
+          let mut node = Node::new(int_label(width), offset, width);
+          node.value = Some(value);
+          attach(&mut stack, &mut roots, node);
+        }
+        Event::Bytes { len, .. } =>
+          attach(&mut stack, &mut roots, Node::new("bytes".to_string(), offset, len)),
+        Event::Sub { len, .. } =>
+          stack.push((Node::new("sub".to_string(), offset, len), Some(offset + len))),
+        Event::Enter { segment, .. } => {
+          let label = match segment {
+            PathSegment::Field(name) => name.to_string(),
+            PathSegment::Index(n) => alloc::format!("[{}]", n),
+          };
+          stack.push((Node::new(label, offset, 0), None));
+        }
+        Event::Exit { .. } => {
+          //close any sub readers left open inside the scope
+          while let Some(&(_, Some(end))) = stack.last() {
+            close(&mut stack, &mut roots, end);
+          }
+          close(&mut stack, &mut roots, offset);
+        }
+      }
+    }
+    while let Some((node, end)) = stack.last() {
+      let end = end.unwrap_or(node.offset + node.len);
+      close(&mut stack, &mut roots, end);
+    }
+    roots
+  }
+}
+
+///Pop the innermost open scope, which ends at `end`
+fn close(stack: &mut Vec<(Node, Option<usize>)>, roots: &mut Vec<Node>, end: usize) {
+  if let Some((mut node, _)) = stack.pop() {
+    node.len = end.saturating_sub(node.offset);
+    attach(stack, roots, node);
+  }
+}
+
+fn attach(stack: &mut [(Node, Option<usize>)], roots: &mut Vec<Node>, node: Node) {
+  match stack.last_mut() {
+    Some(parent) => parent.0.children.push(node),
+    None => roots.push(node)
+  }
+}
+
+fn int_label(width: usize) -> String {
+  alloc::format!("u{}", width * 8)
+}
+
+///One read, or a scope holding several, in an annotated dump
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Node {
+  ///field name, `[index]`, `sub`, `bytes` or the integer type
+  pub label: String,
+  ///absolute offset of the first byte
+  pub offset: usize,
+  pub len: usize,
+  ///the decoded value of integer reads
+  pub value: Option<u64>,
+  pub children: Vec<Node>
+}
+
+impl Node {
+  fn new(label: String, offset: usize, len: usize) -> Node {
+    Node { label, offset, len, value: None, children: Vec::new() }
+  }
+}
+
+///An annotated dump of `bytes`. `Display` gives a tree in the
+///style of Wireshark's packet details, `to_json` the same tree
+///for other tools.
+#[derive(Debug,Clone)]
+pub struct Dump<'a> {
+  bytes: &'a [u8],
+  nodes: Vec<Node>
+}
+
+///Decode a `T` from `bytes` through a traced reader and return it
+///together with the annotated dump. The dump covers everything read
+///before a failure, which is usually where to look.
+pub fn annotate<'a, T: Codec<'a>>(bytes: &'a [u8], trace: &'a Trace) -> (Result<T,DecodeError>, Dump<'a>) {
+  let mut r = Reader::traced(bytes, trace);
+  let ret = T::read(&mut r);
+  (ret, Dump { bytes, nodes: trace.nodes() })
+}
+
+///Hex of at most the first 16 bytes
+fn hex(bytes: &[u8], f: &mut dyn Write) -> fmt::Result {
+  for (i, b) in bytes.iter().take(16).enumerate() {
+    if i > 0 {
+      f.write_char(' ')?;
+    }
+    write!(f, "{:02x}", b)?;
+  }
+  if bytes.len() > 16 {
+    f.write_str(" ...")?;
+  }
+  Ok(())
+}
+
+impl<'a> Dump<'a> {
+
+  ///the top level nodes
+  pub fn nodes(&self) -> &[Node] {
+    &self.nodes
+  }
+
+  fn slice(&self, node: &Node) -> &'a [u8] {
+    let end = core::cmp::min(node.offset + node.len, self.bytes.len());
+    &self.bytes[core::cmp::min(node.offset, end) .. end]
+  }
+
+  fn text(&self, node: &Node, depth: usize, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:04x}  {:width$}{}", node.offset, "", node.label, width = depth * 2)?;
+    if let Some(v) = node.value {
+      write!(f, " = {}", v)?;
+    }
+    if node.children.is_empty() {
+      f.write_str(": ")?;
+      hex(self.slice(node), f)?;
+    } else {
+      write!(f, " ({} bytes)", node.len)?;
+    }
+    f.write_char('\n')?;
+    for child in &node.children {
+      self.text(child, depth + 1, f)?;
+    }
+    Ok(())
+  }
+
+  fn json(&self, node: &Node, out: &mut String) {
+    out.push_str("{\"label\":\"");
+    for c in node.label.chars() {
+      match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+        c => out.push(c)
+      }
+    }
+    let _ = write!(out, "\",\"offset\":{},\"len\":{}", node.offset, node.len);
+    if let Some(v) = node.value {
+      let _ = write!(out, ",\"value\":{}", v);
+    }
+    out.push_str(",\"hex\":\"");
+    for b in self.slice(node) {
+      let _ = write!(out, "{:02x}", b);
+    }
+    out.push_str("\",\"children\":[");
+    for (i, child) in node.children.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      self.json(child, out);
+    }
+    out.push_str("]}");
+  }
+
+  ///The tree as a JSON array of nodes, each with `label`, `offset`,
+  ///`len`, `hex`, `children` and, for integers, `value`
+  pub fn to_json(&self) -> String {
+    let mut out = String::from("[");
+    for (i, node) in self.nodes.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      self.json(node, &mut out);
+    }
+    out.push(']');
+    out
+  }
+}
+
+impl<'a> fmt::Display for Dump<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for node in &self.nodes {
+      self.text(node, 0, f)?;
+    }
+    Ok(())
+  }
+}
+
+impl<'a> Reader<'a> {
+
+  ///Build a Reader which records every read into `trace`,
+  ///including those of `sub` readers made from it
+  pub fn traced(bytes: &'a [u8], trace: &'a Trace) -> Reader<'a> {
+    let mut r = Reader::init(bytes);
+    r.trace = Some(trace);
+    r
+  }
+}
+
+#[test]
+fn test_annotate() {
+  use super::codec::{PayloadU8, read_vec_u16};
+
+  #[derive(Debug)]
+  struct Hello<'a> {
+    version: u16,
+    names: Vec<PayloadU8<'a>>
+  }
+  impl<'a> Codec<'a> for Hello<'a> {
+    fn encode(&self, _: &mut Vec<u8>) { }
+    fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      Ok(Hello {
+        version: r.context("version", |r| r.read_u16().map(|x| x as u16))?,
+        names: r.context("names", read_vec_u16)?
+      })
+    }
+  }
+
+  let bytes = [0x03u8, 0x04, 0x00, 0x05, 0x01, b'a', 0x02, b'b', b'c'];
+  let trace = Trace::new();
+  let (hello, dump) = annotate::<Hello>(&bytes, &trace);
+  let hello = hello.unwrap();
+  assert_eq!(hello.version, 0x0304);
+  assert_eq!(hello.names.len(), 2);
+
+  assert_eq!(dump.to_string(), "\
+0000  version (2 bytes)
+0000    u16 = 772: 03 04
+0002  names (7 bytes)
+0002    u16 = 5: 00 05
+0004    sub (5 bytes)
+0004      [0] (2 bytes)
+0004        u8 = 1: 01
+0005        bytes: 61
+0006      [1] (3 bytes)
+0006        u8 = 2: 02
+0007        bytes: 62 63
+");
+  let names = &dump.nodes()[1];
+  assert_eq!((names.offset, names.len), (2, 7));
+  assert_eq!(names.children[1].children[1].children[0].value, Some(2));
+
+  let json = dump.to_json();
+  assert!(json.starts_with("[{\"label\":\"version\",\"offset\":0,\"len\":2,\"hex\":\"0304\",\"children\":[{\"label\":\"u16\",\"offset\":0,\"len\":2,\"value\":772,\"hex\":\"0304\",\"children\":[]}]}"));
+  assert!(json.ends_with("{\"label\":\"bytes\",\"offset\":7,\"len\":2,\"hex\":\"6263\",\"children\":[]}]}]}]}]"));
+
+  //a failed decode still dumps what was read
+  let trace = Trace::new();
+  let (hello, dump) = annotate::<Hello>(&bytes[.. 6], &trace);
+  assert!(hello.is_err());
+  assert!(dump.to_string().starts_with("0000  version (2 bytes)\n"));
+  assert!(dump.to_string().ends_with("0002  names (2 bytes)\n0002    u16 = 5: 00 05\n"));
+
+  //reads undone by try_parse are not shown
+  let trace = Trace::new();
+  let mut r = Reader::traced(&[1, 2], &trace);
+  assert!(r.try_parse(|r| r.read_u24()).is_err());
+  assert!(r.try_parse(|r| { r.read_u8()?; r.read_u16() }).is_err());
+  assert_eq!(r.read_u16(), Ok(0x0102));
+  assert_eq!(trace.nodes(), vec![Node { label: "u16".to_string(), offset: 0, len: 2, value: Some(0x0102), children: Vec::new() }]);
+}
+
+//readers can be moved and shared between threads, traced or not
+#[cfg(feature = "std")]
+#[test]
+fn test_send_sync() {
+  fn check<T: Send + Sync>() {}
+  check::<Reader>();
+  check::<Trace>();
+  check::<super::view::VecView<u8>>();
+  check::<super::view::VecIter<u8>>();
+}
@@ -0,0 +1,264 @@
+use core::fmt;
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+///Message carried by `DecodeError::Custom`. Without `alloc`
+///only static strings are available.
+#[cfg(feature = "alloc")]
+pub type ErrorMessage = Cow<'static, str>;
+#[cfg(not(feature = "alloc"))]
+pub type ErrorMessage = &'static str;
+
+///Describes why a `Reader` failed to decode a value.
+///
+///Every variant carries `offset`, the position the failure was
+///detected at within the buffer of the outermost `Reader`, so
+///it is not reset by `sub` readers.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum DecodeError {
+  ///The buffer ended before `needed` bytes could be read,
+  ///only `available` remained.
+  Truncated { offset: usize, needed: usize, available: usize },
+  ///A partial `Reader` ran out of input. At least `needed`
+  ///more bytes must arrive before decoding can progress.
+  Incomplete { offset: usize, needed: usize },
+  ///A length prefix was decoded that cannot be represented
+  ///as a `usize` on this platform.
+  LengthOverflow { offset: usize, len: u64 },
+  ///A length prefix gave `len`, outside the `min..=max` range
+  ///the field is declared with.
+  LengthOutOfRange { offset: usize, len: usize, min: usize, max: usize },
+  ///A vector of `size` byte elements had a length prefix of
+  ///`len`, which is not a multiple of it.
+  LengthNotMultiple { offset: usize, len: usize, size: usize },
+  ///Bytes were present but did not form a legal value.
+  InvalidValue { offset: usize, what: &'static str },
+  ///Input asked for more than the reader's `DecodeLimits`
+  ///allow, `limit` names the field.
+  LimitExceeded { offset: usize, limit: &'static str },
+  ///Decoding finished with `left` bytes still unread.
+  TrailingData { offset: usize, left: usize },
+  ///Error raised by a user `Codec` implementation.
+  Custom { offset: usize, msg: ErrorMessage },
+  ///`source` was raised while decoding the field at `path`,
+  ///added by `Reader::context`.
+  #[cfg(feature = "alloc")]
+  Context { path: ErrorPath, source: Box<DecodeError> },
+}
+
+///One step of an `ErrorPath`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PathSegment {
+  ///a named field
+  Field(&'static str),
+  ///an element of a vector
+  Index(usize),
+}
+
+///The fields leading to a decode error, outermost first.
+///Displays as `hello.extensions[3].server_name`.
+#[cfg(feature = "alloc")]
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct ErrorPath(pub Vec<PathSegment>);
+
+#[cfg(feature = "alloc")]
+impl fmt::Display for ErrorPath {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, seg) in self.0.iter().enumerate() {
+      match *seg {
+        PathSegment::Field(name) if i == 0 => write!(f, "{}", name)?,
+        PathSegment::Field(name) => write!(f, ".{}", name)?,
+        PathSegment::Index(n) => write!(f, "[{}]", n)?,
+      }
+    }
+    Ok(())
+  }
+}
+
+impl DecodeError {
+
+  ///Offset into the reader the error was raised at
+  pub fn offset(&self) -> usize {
+    match *self {
+      DecodeError::Truncated { offset, .. } |
+      DecodeError::Incomplete { offset, .. } |
+      DecodeError::LengthOverflow { offset, .. } |
+      DecodeError::LengthOutOfRange { offset, .. } |
+      DecodeError::LengthNotMultiple { offset, .. } |
+      DecodeError::InvalidValue { offset, .. } |
+      DecodeError::LimitExceeded { offset, .. } |
+      DecodeError::TrailingData { offset, .. } |
+      DecodeError::Custom { offset, .. } => offset,
+      #[cfg(feature = "alloc")]
+      DecodeError::Context { ref source, .. } => source.offset()
+    }
+  }
+
+  ///The error itself, without any `Context` around it
+  pub fn root(&self) -> &DecodeError {
+    match *self {
+      #[cfg(feature = "alloc")]
+      DecodeError::Context { ref source, .. } => source,
+      _ => self
+    }
+  }
+
+  ///Path of the field which failed, if `Reader::context`
+  ///was used while decoding it
+  #[cfg(feature = "alloc")]
+  pub fn path(&self) -> Option<&ErrorPath> {
+    match *self {
+      DecodeError::Context { ref path, .. } => Some(path),
+      _ => None
+    }
+  }
+
+  ///Record that this error happened inside `segment`. Without
+  ///`alloc` there is nowhere to keep the path and the error is
+  ///returned as is.
+  pub fn in_context(self, segment: PathSegment) -> DecodeError {
+    #[cfg(feature = "alloc")]
+    {
+      match self {
+        DecodeError::Context { mut path, source } => {
+          path.0.insert(0, segment);
+          DecodeError::Context { path, source }
+        }
+        e => DecodeError::Context { path: ErrorPath(alloc::vec![segment]), source: Box::new(e) }
+      }
+    }
+    #[cfg(not(feature = "alloc"))]
+    {
+      let _ = segment;
+      self
+    }
+  }
+
+  ///True when more input may turn this error into a successful
+  ///decode, see `Reader::partial`
+  pub fn is_incomplete(&self) -> bool {
+    matches!(*self.root(), DecodeError::Incomplete { .. })
+  }
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      DecodeError::Truncated { offset, needed, available } =>
+        write!(f, "truncated input at offset {}: needed {} bytes, {} available", offset, needed, available),
+      DecodeError::Incomplete { offset, needed } =>
+        write!(f, "incomplete input at offset {}: {} more bytes needed", offset, needed),
+      DecodeError::LengthOverflow { offset, len } =>
+        write!(f, "length prefix {} at offset {} does not fit in usize", len, offset),
+      DecodeError::LengthOutOfRange { offset, len, min, max } =>
+        write!(f, "length {} at offset {} outside {}..={}", len, offset, min, max),
+      DecodeError::LengthNotMultiple { offset, len, size } =>
+        write!(f, "length {} at offset {} is not a multiple of element size {}", len, offset, size),
+      DecodeError::InvalidValue { offset, what } =>
+        write!(f, "invalid {} at offset {}", what, offset),
+      DecodeError::LimitExceeded { offset, limit } =>
+        write!(f, "decode limit {} exceeded at offset {}", limit, offset),
+      DecodeError::TrailingData { offset, left } =>
+        write!(f, "{} bytes of trailing data at offset {}", left, offset),
+      DecodeError::Custom { offset, ref msg } =>
+        write!(f, "{} at offset {}", msg, offset),
+      #[cfg(feature = "alloc")]
+      DecodeError::Context { ref path, ref source } =>
+        write!(f, "{} in {}", source, path),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError { }
+
+///Describes why a value could not be encoded.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum EncodeError {
+  ///`actual` bytes were to be written under a length prefix
+  ///which can express at most `max`.
+  LengthOverflow { max: u64, actual: usize },
+  ///`actual` bytes were to be written for a field declared
+  ///to be `min..=max` bytes long.
+  LengthOutOfRange { min: usize, max: usize, actual: usize },
+  ///`actual` is larger than the `max` the encoding can express.
+  ValueOverflow { max: u64, actual: u64 },
+  ///A fixed size output buffer had only `available` bytes
+  ///left where `needed` were to be written.
+  BufferFull { needed: usize, available: usize },
+}
+
+impl fmt::Display for EncodeError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      EncodeError::LengthOverflow { max, actual } =>
+        write!(f, "length {} exceeds prefix maximum of {}", actual, max),
+      EncodeError::LengthOutOfRange { min, max, actual } =>
+        write!(f, "length {} outside {}..={}", actual, min, max),
+      EncodeError::ValueOverflow { max, actual } =>
+        write!(f, "value {} exceeds encoding maximum of {}", actual, max),
+      EncodeError::BufferFull { needed, available } =>
+        write!(f, "output buffer full: needed {} bytes, {} available", needed, available),
+    }
+  }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError { }
+
+///Error from `CodecFramed`, which must also carry the I/O errors
+///of the underlying stream.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub enum FramedError {
+  Io(std::io::Error),
+  Decode(DecodeError),
+  Encode(EncodeError),
+}
+
+#[cfg(feature = "tokio")]
+impl From<std::io::Error> for FramedError {
+  fn from(e: std::io::Error) -> Self {
+    FramedError::Io(e)
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl From<DecodeError> for FramedError {
+  fn from(e: DecodeError) -> Self {
+    FramedError::Decode(e)
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl From<EncodeError> for FramedError {
+  fn from(e: EncodeError) -> Self {
+    FramedError::Encode(e)
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl fmt::Display for FramedError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      FramedError::Io(ref e) => write!(f, "i/o error: {}", e),
+      FramedError::Decode(ref e) => write!(f, "decode error: {}", e),
+      FramedError::Encode(ref e) => write!(f, "encode error: {}", e),
+    }
+  }
+}
+
+#[cfg(feature = "tokio")]
+impl std::error::Error for FramedError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match *self {
+      FramedError::Io(ref e) => Some(e),
+      FramedError::Decode(ref e) => Some(e),
+      FramedError::Encode(ref e) => Some(e),
+    }
+  }
+}
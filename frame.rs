@@ -0,0 +1,232 @@
+use alloc::vec::Vec;
+
+use super::codec::{ByteOrder, Codec, DecodeLimits, Reader};
+use super::error::DecodeError;
+
+///Find the frame at the start of `buf`, which has a `width` byte
+///length prefix, returning the prefix and body length. Gives
+///`Incomplete` if the frame has not fully arrived. A body longer
+///than `max_len` is refused as soon as the prefix is seen, before
+///any of it need be buffered.
+pub(crate) fn peek_frame(buf: &[u8], width: usize, order: ByteOrder, max_len: usize) -> Result<(usize, usize),DecodeError> {
+  let mut r = Reader::partial(buf);
+  r.set_order(order);
+  let len = match width {
+    2 => r.read_u16()?,
+    3 => r.read_u24()?,
+    _ => r.read_u32()?
+  };
+  if len > max_len {
+    return Err(r.limit_exceeded("max_payload_len"));
+  }
+  r.take(len)?;
+  Ok((width, len))
+}
+
+///FrameDecoder collects bytes as they arrive, for example from a
+///socket, until a whole length prefixed frame is buffered. Each
+///complete frame is then decoded from a `Reader` over its body.
+///
+///```ignore
+///let mut frames = FrameDecoder::u16();
+///frames.push(&chunk);
+///while let Some(msg) = frames.decode::<Message>()? {
+///  handle(msg);
+///}
+///```
+pub struct FrameDecoder {
+  buf: Vec<u8>,
+  //bytes at the front of buf belonging to frames already handed out
+  consumed: usize,
+  width: usize,
+  order: ByteOrder,
+  limits: DecodeLimits
+}
+
+impl FrameDecoder {
+
+  ///Frames whose body is preceded by a `width` byte length
+  fn with_width(width: usize) -> FrameDecoder {
+    FrameDecoder {
+      buf: Vec::new(),
+      consumed: 0,
+      width,
+      order: ByteOrder::BigEndian,
+      limits: DecodeLimits::UNLIMITED
+    }
+  }
+
+  ///Frames with a u16 length prefix
+  pub fn u16() -> FrameDecoder {
+    FrameDecoder::with_width(2)
+  }
+
+  ///Frames with a u24 length prefix
+  pub fn u24() -> FrameDecoder {
+    FrameDecoder::with_width(3)
+  }
+
+  ///Frames with a u32 length prefix
+  pub fn u32() -> FrameDecoder {
+    FrameDecoder::with_width(4)
+  }
+
+  ///byte order of the length prefix, big endian by default
+  pub fn order(&self) -> ByteOrder {
+    self.order
+  }
+
+  ///change the byte order of the length prefix, frame bodies
+  ///are still read big endian
+  pub fn set_order(&mut self, order: ByteOrder) {
+    self.order = order;
+  }
+
+  ///Enforce `limits` on every frame. A frame longer than
+  ///`max_payload_len` is an error as soon as its prefix arrives,
+  ///and the stream cannot carry on past it. Unlimited by default.
+  pub fn with_limits(mut self, limits: DecodeLimits) -> FrameDecoder {
+    self.limits = limits;
+    self
+  }
+
+  ///limits enforced on every frame
+  pub fn limits(&self) -> DecodeLimits {
+    self.limits
+  }
+
+  ///drop frames handed out by earlier calls
+  fn compact(&mut self) {
+    if self.consumed > 0 {
+      self.buf.drain(.. self.consumed);
+      self.consumed = 0;
+    }
+  }
+
+  ///Append newly received bytes
+  pub fn push(&mut self, data: &[u8]) {
+    self.compact();
+    self.buf.extend_from_slice(data);
+  }
+
+  ///number of bytes buffered which are not yet part of a returned frame
+  pub fn buffered(&self) -> usize {
+    self.buf.len() - self.consumed
+  }
+
+  ///Minimum number of bytes which must still be pushed before the
+  ///next frame is complete, 0 if one is ready now
+  pub fn needed(&self) -> usize {
+    match self.peek() {
+      Ok(_) => 0,
+      Err(DecodeError::Incomplete { needed, .. }) => needed,
+      //a prefix which does not fit a usize can never complete
+      Err(_) => usize::MAX
+    }
+  }
+
+  ///Locate the next frame, returning the prefix and body length
+  fn peek(&self) -> Result<(usize, usize),DecodeError> {
+    peek_frame(&self.buf[self.consumed ..], self.width, self.order, self.limits.max_payload_len)
+  }
+
+  ///Return a Reader over the body of the next frame, or None if it
+  ///has not fully arrived. The frame is consumed either way once
+  ///returned. The reader enforces the decoder's limits.
+  pub fn next_frame(&mut self) -> Result<Option<Reader<'_>>,DecodeError> {
+    self.compact();
+    let (prefix, len) = match self.peek() {
+      Ok(x) => x,
+      Err(DecodeError::Incomplete { .. }) => return Ok(None),
+      Err(e) => return Err(e)
+    };
+    self.consumed = prefix + len;
+    let mut r = Reader::init(&self.buf[prefix .. prefix + len]);
+    r.set_limits(self.limits);
+    Ok(Some(r))
+  }
+
+  ///Decode the next frame as a `T`, or None if it has not fully
+  ///arrived. The whole body must be consumed by `T::read`. A frame
+  ///which fails to decode is still dropped, so the stream can carry
+  ///on with the one after it.
+  pub fn decode<'a, T: Codec<'a>>(&'a mut self) -> Result<Option<T>,DecodeError> {
+    let mut r = match self.next_frame()? {
+      Some(r) => r,
+      None => return Ok(None)
+    };
+    let ret = T::read(&mut r)?;
+    r.finish()?;
+    Ok(Some(ret))
+  }
+}
+
+#[test]
+fn test_partial_reader() {
+  let bytes = [0u8, 5, 1, 2];
+  let mut r = Reader::partial(&bytes);
+  assert!(r.is_partial());
+  let len = r.read_u16().unwrap();
+  assert_eq!(r.take(len), Err(DecodeError::Incomplete { offset: 2, needed: 3 }));
+  assert!(r.take(len).unwrap_err().is_incomplete());
+
+  //a sub reader knows its own length, running out is malformed
+  let mut sub = r.sub(2).unwrap();
+  assert!(!sub.is_partial());
+  assert_eq!(sub.read_u24(), Err(DecodeError::Truncated { offset: 2, needed: 3, available: 2 }));
+
+  //complete input decodes the same as with a normal reader
+  let mut r = Reader::partial(&bytes[.. 2]);
+  assert_eq!(r.read_u16(), Ok(5));
+}
+
+#[test]
+fn test_frame_decoder() {
+  use super::codec::PayloadU8;
+
+  let mut frames = FrameDecoder::u16();
+  assert_eq!(frames.needed(), 2);
+  frames.push(&[0]);
+  assert_eq!(frames.needed(), 1);
+  assert!(frames.decode::<PayloadU8>().unwrap().is_none());
+  frames.push(&[4, 3, b'a']);
+  assert_eq!(frames.needed(), 2);
+  assert!(frames.next_frame().unwrap().is_none());
+
+  //the rest of the first frame and all of the second in one go
+  frames.push(&[b'b', b'c', 0, 2, 1, b'z', 0]);
+  let p = frames.decode::<PayloadU8>().unwrap().unwrap();
+  assert_eq!(p.to_slice(), b"abc");
+  let p = frames.decode::<PayloadU8>().unwrap().unwrap();
+  assert_eq!(p.to_slice(), b"z");
+  assert_eq!(frames.buffered(), 1);
+  assert!(frames.decode::<PayloadU8>().unwrap().is_none());
+
+  //a frame with leftover bytes is an error, but is skipped
+  frames.push(&[3, 1, b'x', b'y', 0, 0]);
+  assert_eq!(frames.decode::<PayloadU8>().unwrap_err(),
+    DecodeError::TrailingData { offset: 2, left: 1 });
+  let mut r = frames.next_frame().unwrap().unwrap();
+  assert!(!r.any_left());
+  assert!(r.read_u8().is_err());
+  assert_eq!(frames.buffered(), 0);
+
+  //little endian u24 prefixes
+  let mut frames = FrameDecoder::u24();
+  frames.set_order(ByteOrder::LittleEndian);
+  frames.push(&[1, 0, 0, 0xaa, 2, 0, 0, 0xab, 0xcd]);
+  assert_eq!(frames.next_frame().unwrap().unwrap().rest(), &[0xaa]);
+  let mut r = frames.next_frame().unwrap().unwrap();
+  assert_eq!(r.order(), ByteOrder::BigEndian);
+  assert_eq!(r.read_u16(), Ok(0xabcd));
+
+  //an oversized frame is refused from its prefix alone, and the
+  //limits carry into the frame's reader
+  let limits = DecodeLimits { max_payload_len: 4, ..DecodeLimits::UNLIMITED };
+  let mut frames = FrameDecoder::u16().with_limits(limits);
+  frames.push(&[0, 2, 1, b'a', 0, 5]);
+  assert_eq!(frames.next_frame().unwrap().unwrap().limits(), limits);
+  assert_eq!(frames.decode::<PayloadU8>(),
+    Err(DecodeError::LimitExceeded { offset: 2, limit: "max_payload_len" }));
+  assert_eq!(frames.needed(), usize::MAX);
+}
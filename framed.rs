@@ -0,0 +1,262 @@
+use core::marker::PhantomData;
+
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+use super::codec::{ByteOrder, Codec, DecodeLimits, Reader, check_len};
+use super::error::{DecodeError, FramedError};
+use super::frame::peek_frame;
+
+///CodecFramed adapts any `Codec` type to tokio-util's `Decoder`
+///and `Encoder`, so messages can be sent and received through
+///`Framed`. Every message is wrapped in an outer length prefix.
+///
+///```ignore
+///let mut framed = Framed::new(stream, CodecFramed::<Message>::u16());
+///framed.send(msg).await?;
+///let reply = framed.next().await;
+///```
+///
+///Items are decoded out of a buffer which is reused, so `T` must
+///not borrow from its input.
+pub struct CodecFramed<T> {
+  width: usize,
+  order: ByteOrder,
+  limits: DecodeLimits,
+  _item: PhantomData<fn() -> T>
+}
+
+impl<T> CodecFramed<T> {
+
+  ///Messages whose body is preceded by a `width` byte length
+  fn with_width(width: usize) -> CodecFramed<T> {
+    CodecFramed { width, order: ByteOrder::BigEndian, limits: DecodeLimits::UNLIMITED, _item: PhantomData }
+  }
+
+  ///Messages with a u16 length prefix
+  pub fn u16() -> CodecFramed<T> {
+    CodecFramed::with_width(2)
+  }
+
+  ///Messages with a u24 length prefix
+  pub fn u24() -> CodecFramed<T> {
+    CodecFramed::with_width(3)
+  }
+
+  ///Messages with a u32 length prefix
+  pub fn u32() -> CodecFramed<T> {
+    CodecFramed::with_width(4)
+  }
+
+  ///byte order of the length prefix, big endian by default
+  pub fn order(&self) -> ByteOrder {
+    self.order
+  }
+
+  ///change the byte order of the length prefix, frame bodies
+  ///are still read big endian
+  pub fn set_order(&mut self, order: ByteOrder) {
+    self.order = order;
+  }
+
+  ///Enforce `limits` while decoding. A frame longer than
+  ///`max_payload_len` is an error as soon as its prefix arrives,
+  ///before the rest of it is buffered. Unlimited by default.
+  pub fn with_limits(mut self, limits: DecodeLimits) -> CodecFramed<T> {
+    self.limits = limits;
+    self
+  }
+
+  ///limits enforced while decoding
+  pub fn limits(&self) -> DecodeLimits {
+    self.limits
+  }
+}
+
+impl<T> Clone for CodecFramed<T> {
+  fn clone(&self) -> Self {
+    CodecFramed { width: self.width, order: self.order, limits: self.limits, _item: PhantomData }
+  }
+}
+
+impl<T> core::fmt::Debug for CodecFramed<T> {
+  fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    f.debug_struct("CodecFramed")
+      .field("width", &self.width)
+      .field("order", &self.order)
+      .field("limits", &self.limits)
+      .finish()
+  }
+}
+
+impl<T> Decoder for CodecFramed<T>
+where T: for<'a> Codec<'a>
+{
+  type Item = T;
+  type Error = FramedError;
+
+  fn decode(&mut self, src: &mut BytesMut) -> Result<Option<T>,FramedError> {
+    let (prefix, len) = match peek_frame(&src[..], self.width, self.order, self.limits.max_payload_len) {
+      Ok(x) => x,
+      Err(DecodeError::Incomplete { .. }) => return Ok(None),
+      Err(e) => return Err(e.into())
+    };
+    //the frame is dropped even if it fails to decode, shared
+    //payloads within it keep pointing into the received buffer
+    let frame = src.split_to(prefix + len).freeze().slice(prefix ..);
+    //the body is read in the default order, which is only
+    //changed for the prefix
+    let mut r = Reader::shared(&frame);
+    r.set_limits(self.limits);
+    let ret = T::read(&mut r)?;
+    r.finish()?;
+    Ok(Some(ret))
+  }
+}
+
+impl<T> Encoder<T> for CodecFramed<T>
+where T: for<'a> Codec<'a>
+{
+  type Error = FramedError;
+
+  fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(),FramedError> {
+    //everything is checked up front, so the prefix and body are
+    //written straight into `dst` and nothing is written on error.
+    //Items without an `exact_len` are encoded once to size them.
+    let body = match item.exact_len() {
+      Some(_) => None,
+      None => Some(item.try_get_encoding()?)
+    };
+    let len = match body {
+      Some(ref body) => body.len(),
+      None => item.try_encoded_len()?
+    };
+    check_len(len, (1u64 << (8 * self.width)) - 1)?;
+    dst.reserve(self.width + len);
+    match self.order {
+      ByteOrder::BigEndian => dst.put_uint(len as u64, self.width),
+      ByteOrder::LittleEndian => dst.put_uint_le(len as u64, self.width)
+    }
+    match body {
+      Some(body) => dst.put_slice(&body),
+      None => item.encode_buf(dst)
+    }
+    Ok(())
+  }
+}
+
+//a u16 read in the reader's order
+#[cfg(test)]
+#[derive(Debug,PartialEq)]
+struct Word(usize);
+
+#[cfg(test)]
+impl<'a> Codec<'a> for Word {
+  fn encode(&self, bytes: &mut alloc::vec::Vec<u8>) {
+    bytes.extend_from_slice(&(self.0 as u16).to_be_bytes());
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    r.read_u16().map(Word)
+  }
+}
+
+#[test]
+fn test_codec_framed() {
+  use super::error::EncodeError;
+
+  let mut codec = CodecFramed::<(u8, u16)>::u16();
+  let mut buf = BytesMut::new();
+  codec.encode((1, 0x0203), &mut buf).unwrap();
+  codec.encode((4, 0x0506), &mut buf).unwrap();
+  assert_eq!(&buf[..], &[0,3, 1,2,3, 0,3, 4,5,6]);
+
+  //bytes arrive one at a time
+  let wire = buf.split();
+  let mut out = Vec::new();
+  for b in wire.iter() {
+    buf.extend_from_slice(&[*b]);
+    if let Some(item) = codec.decode(&mut buf).unwrap() {
+      out.push(item);
+    }
+  }
+  assert_eq!(out, vec![(1, 0x0203), (4, 0x0506)]);
+  assert!(buf.is_empty());
+
+  //a malformed frame is reported, then skipped
+  let mut codec = CodecFramed::<u16>::u24();
+  codec.set_order(ByteOrder::LittleEndian);
+  buf.extend_from_slice(&[3,0,0, 1,2,3, 2,0,0, 0xab,0xcd]);
+  match codec.decode(&mut buf) {
+    Err(FramedError::Decode(DecodeError::TrailingData { offset: 2, left: 1 })) => {},
+    x => panic!("unexpected {:?}", x.map(|_| ()))
+  }
+  assert_eq!(codec.decode(&mut buf).unwrap(), Some(0xabcd));
+  //and the body reader is not given the prefix's order
+  let mut codec = CodecFramed::<Word>::u16();
+  codec.set_order(ByteOrder::LittleEndian);
+  buf.extend_from_slice(&[2,0, 0xab,0xcd]);
+  assert_eq!(codec.decode(&mut buf).unwrap(), Some(Word(0xabcd)));
+
+  //limits apply to the frame length and within the frame
+  let limits = DecodeLimits { max_payload_len: 3, ..DecodeLimits::UNLIMITED };
+  let mut codec = CodecFramed::<super::shared::SharedPayloadU8>::u16().with_limits(limits);
+  buf.extend_from_slice(&[0,2, 1,b'a', 0,4]);
+  assert_eq!(codec.decode(&mut buf).unwrap().unwrap().to_slice(), b"a");
+  match codec.decode(&mut buf) {
+    Err(FramedError::Decode(DecodeError::LimitExceeded { offset: 2, limit: "max_payload_len" })) => {},
+    x => panic!("unexpected {:?}", x.map(|_| ()))
+  }
+  buf.clear();
+  buf.extend_from_slice(&[0,3, 4,b'a',b'b']);
+  match codec.decode(&mut buf) {
+    Err(FramedError::Decode(DecodeError::LimitExceeded { offset: 1, limit: "max_payload_len" })) => {},
+    x => panic!("unexpected {:?}", x.map(|_| ()))
+  }
+  buf.clear();
+
+  //bodies too long for the prefix are not written
+  let mut codec = CodecFramed::<[u8; 0x10000]>::u16();
+  match codec.encode([0u8; 0x10000], &mut buf) {
+    Err(FramedError::Encode(EncodeError::LengthOverflow { max: 0xffff, actual: 0x10000 })) => {},
+    x => panic!("unexpected {:?}", x)
+  }
+  assert!(buf.is_empty());
+}
+
+//messages sent through `Framed` arrive whole at the other end
+//of a stream, even when it passes them on a few bytes at a time
+#[cfg(test)]
+#[tokio::test]
+async fn test_framed_duplex() {
+  use futures_util::{SinkExt, StreamExt};
+  use tokio_util::codec::Framed;
+  use super::shared::SharedPayloadU8;
+
+  let (a, b) = tokio::io::duplex(3);
+  let mut tx = Framed::new(a, CodecFramed::<(u16, SharedPayloadU8)>::u16());
+  let mut rx = Framed::new(b, CodecFramed::<(u16, SharedPayloadU8)>::u16());
+  let sent = vec![
+    (1, SharedPayloadU8::from_slice(b"hello")),
+    (2, SharedPayloadU8::from_slice(b"")),
+    (3, SharedPayloadU8::from(vec![7u8; 200]))
+  ];
+  let send = async {
+    for msg in sent.clone() {
+      tx.send(msg).await.unwrap();
+    }
+    tx
+  };
+  let recv = async {
+    let mut got = Vec::new();
+    while got.len() < 3 {
+      got.push(rx.next().await.unwrap().unwrap());
+    }
+    got
+  };
+  let (tx, got) = tokio::join!(send, recv);
+  assert_eq!(got, sent);
+
+  //the stream ends cleanly once the sender is gone
+  drop(tx);
+  assert!(rx.next().await.is_none());
+}
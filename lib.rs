@@ -1,10 +1,139 @@
 
+//! TLS style binary encoding and decoding.
+//!
+//! Cargo features:
+//!
+//! - `std` (default): `std::error::Error` impls, implies `alloc`.
+//! - `alloc`: owned payloads, `Writer`, the `Codec` trait, the
+//!   `Vec` based encoders and annotated dumps. Without it only `Reader`, borrowed
+//!   payloads and `SliceWriter` are available, which needs no
+//!   allocator at all.
+//! - `derive`: `#[derive(Codec)]` and `#[derive(IntoOwned)]`, implies
+//!   `alloc`.
+//! - `bytes`: payloads backed by refcounted `bytes::Bytes` and
+//!   encoding into any `BufMut`, implies `alloc`.
+//! - `tokio`: `CodecFramed`, a tokio-util `Decoder`/`Encoder` for
+//!   any `Codec` type, implies `std` and `bytes`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+//the original tests compare with `true` and `false`
+#![cfg_attr(test, allow(clippy::bool_assert_comparison))]
+
+//tests use `vec!` and `format!` with or without std
+#[cfg(feature = "alloc")]
+#[cfg_attr(test, macro_use)]
+extern crate alloc;
+
+//lets `#[derive(Codec)]` output refer to `::codecbase` from inside
+//this crate as well
+extern crate self as codecbase;
+
 #[macro_use]
 mod macros;
+mod error;
 mod codec;
+mod prefix;
+#[cfg(feature = "alloc")]
+mod primitive;
+mod varint;
+#[cfg(feature = "alloc")]
+mod frame;
+#[cfg(feature = "alloc")]
+mod view;
+#[cfg(feature = "alloc")]
+mod span;
+#[cfg(feature = "alloc")]
+mod dump;
+#[cfg(feature = "alloc")]
+mod bounded;
+#[cfg(feature = "alloc")]
+mod counted;
+#[cfg(feature = "alloc")]
+mod string;
+#[cfg(feature = "alloc")]
+mod owned;
+#[cfg(feature = "bytes")]
+mod shared;
+#[cfg(feature = "tokio")]
+mod framed;
 
-pub use codec::{Codec,Reader};
-//pub use base::{Payload,PayloadU24,PayloadU16,PayloadU8};
+pub use error::{DecodeError,EncodeError,ErrorMessage,PathSegment};
+#[cfg(feature = "alloc")]
+pub use error::ErrorPath;
+pub use codec::{Reader,Checkpoint,SliceWriter,ByteOrder,DecodeLimits,Bytes};
+pub use codec::{Payload,PayloadU8,PayloadU16,PayloadU24,PayloadU32,PayloadU64};
+pub use codec::{PayloadU16Le,PayloadU24Le,PayloadU32Le,PayloadU64Le};
+pub use codec::{PayloadVarint,PayloadQuicVarint};
+pub use prefix::{LengthPrefix,LengthPrefixed,U8,U16,U24,U32,U64,U16Le,U24Le,U32Le,U64Le,Varint,QuicVarint};
+pub use varint::{QUIC_VARINT_MAX,zigzag_encode,zigzag_decode,leb128_len,quic_varint_len};
+#[cfg(feature = "alloc")]
+pub use codec::{Codec,Writer};
+#[cfg(feature = "alloc")]
+pub use frame::FrameDecoder;
+#[cfg(feature = "alloc")]
+pub use view::{VecIter,VecView};
+#[cfg(feature = "alloc")]
+pub use span::Spanned;
+#[cfg(feature = "alloc")]
+pub use dump::{Trace,Node,Dump,annotate};
+#[cfg(feature = "alloc")]
+pub use bounded::{Bounded,BoundedVec,BoundedPayload};
+#[cfg(feature = "alloc")]
+pub use string::{Str,StrRule,Utf8,Ascii,Hostname,Printable};
+#[cfg(feature = "alloc")]
+pub use string::{StrU8,StrU16,StrU24,StrU32,StrU64,AsciiStrU8,AsciiStrU16,AsciiStrU24,AsciiStrU32,AsciiStrU64,HostnameU16,PrintableString};
+#[cfg(feature = "alloc")]
+pub use counted::{CountedVec,CountedVecU8,CountedVecU16,CountedVecU24,CountedVecU32,CountedVecU64};
+#[cfg(feature = "alloc")]
+pub use counted::{encode_counted_vec_u8,encode_counted_vec_u16,encode_counted_vec_u24,encode_counted_vec_u32,encode_counted_vec_u64};
+#[cfg(feature = "alloc")]
+pub use counted::{try_encode_counted_vec_u8,try_encode_counted_vec_u16,try_encode_counted_vec_u24,try_encode_counted_vec_u32,try_encode_counted_vec_u64};
+#[cfg(feature = "alloc")]
+pub use counted::{read_counted_vec_u8,read_counted_vec_u16,read_counted_vec_u24,read_counted_vec_u32,read_counted_vec_u64};
+#[cfg(feature = "alloc")]
+pub use owned::{IntoOwned,DecodeOwned};
+#[cfg(feature = "bytes")]
+pub use shared::{SharedPayloadU8,SharedPayloadU16,SharedPayloadU24,SharedPayloadU32,SharedPayloadU64};
+#[cfg(feature = "bytes")]
+pub use shared::{decode_bytes,decode_bytes_with_limits,encode_vec_u8_buf,encode_vec_u16_buf,encode_vec_u24_buf,encode_vec_u32_buf,encode_vec_u64_buf};
+#[cfg(feature = "bytes")]
+pub use shared::{try_encode_vec_u8_buf,try_encode_vec_u16_buf,try_encode_vec_u24_buf,try_encode_vec_u32_buf,try_encode_vec_u64_buf};
+#[cfg(feature = "tokio")]
+pub use framed::CodecFramed;
+#[cfg(feature = "tokio")]
+pub use error::FramedError;
+#[cfg(feature = "alloc")]
+pub use varint::{encode_zigzag,encode_leb128_u64,encode_sleb128,encode_quic_varint,try_encode_quic_varint};
+pub use codec::{decode_u8,decode_u16,decode_u24,decode_u32,decode_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{encode_u8,encode_u16,encode_u24,encode_u32,encode_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{encode_u16_le,encode_u24_le,encode_u32_le,encode_u64_le};
+#[cfg(feature = "alloc")]
+pub use codec::{encode_vec_u8,encode_vec_u16,encode_vec_u24,encode_vec_u32,encode_vec_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{try_encode_vec_u8,try_encode_vec_u16,try_encode_vec_u24,try_encode_vec_u32,try_encode_vec_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{encoded_len_vec_u8,encoded_len_vec_u16,encoded_len_vec_u24,encoded_len_vec_u32,encoded_len_vec_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{try_encoded_len_vec_u8,try_encoded_len_vec_u16,try_encoded_len_vec_u24,try_encoded_len_vec_u32,try_encoded_len_vec_u64};
+#[cfg(feature = "alloc")]
+pub use codec::{read_vec_u8,read_vec_u16,read_vec_u24,read_vec_u32,read_vec_u64};
+
+#[cfg(feature = "derive")]
+pub use codecbase_derive::{Codec,IntoOwned};
+
+//paths used by macro and derive output, which must resolve
+//whether or not the caller links std
+#[doc(hidden)]
+pub mod __private {
+  #[cfg(feature = "alloc")]
+  pub use alloc::vec::Vec;
+  #[cfg(feature = "alloc")]
+  pub use super::codec::items_exact_len;
+  #[cfg(feature = "bytes")]
+  pub use bytes::BufMut;
+}
 
 
 //
@@ -57,7 +186,7 @@ fn validate_reader() {
     {
         //you cannot borrow more data then there is left
         let x = r.sub(10);
-        assert!(x.is_none());
+        assert!(x.is_err());
     }{
         //create a new reader of all the data left in r
         let x = r.sub(8).unwrap();
@@ -76,3 +205,108 @@ fn validate_reader() {
     }
 }
 
+
+//Derive
+#[cfg(feature = "derive")]
+#[test]
+fn validate_derive() {
+    use alloc::borrow::Cow;
+    use alloc::string::ToString;
+    use alloc::vec::Vec;
+
+    #[derive(Debug,PartialEq,Codec,IntoOwned)]
+    struct Extension<'a> {
+        kind: PayloadU8<'a>,
+        body: PayloadU16<'a>,
+    }
+
+    #[derive(Debug,PartialEq,Codec,IntoOwned)]
+    struct Hello<'a> {
+        random: PayloadU8<'a>,
+        #[codec(prefix = "u16")]
+        extensions: Vec<Extension<'a>>,
+    }
+
+    #[derive(Debug,PartialEq,Codec,IntoOwned)]
+    #[codec(tag = "u8")]
+    enum Message<'a> {
+        #[codec(value = 1)]
+        Hello(Hello<'a>),
+        #[codec(value = 2)]
+        Bye { reason: PayloadU8<'a> },
+        #[codec(value = 0xff)]
+        Ping,
+    }
+
+    let msg = Message::Hello(Hello {
+        random: PayloadU8::from_slice(b"rand"),
+        extensions: vec![
+            Extension { kind: PayloadU8::from_slice(b"a"), body: PayloadU16::from_slice(b"xyz") },
+            Extension { kind: PayloadU8::from_slice(b""), body: PayloadU16::from_slice(b"") },
+        ],
+    });
+    let bytes = msg.get_encoding();
+    assert_eq!(bytes, vec![
+        1,
+        4, b'r',b'a',b'n',b'd',
+        0,10,
+            1,b'a', 0,3,b'x',b'y',b'z',
+            0, 0,0,
+    ]);
+    assert_eq!(msg.try_get_encoding().unwrap(), bytes);
+    assert_eq!(msg.encoded_len(), bytes.len());
+    assert_eq!(msg.try_encoded_len(), Ok(bytes.len()));
+    assert_eq!(Message::Ping.encoded_len(), 1);
+    #[cfg(feature = "bytes")]
+    {
+        let mut buf = ::bytes::BytesMut::new();
+        msg.try_encode_buf(&mut buf).unwrap();
+        Message::Ping.encode_buf(&mut buf);
+        assert_eq!(&buf[.. bytes.len()], &bytes[..]);
+        assert_eq!(&buf[bytes.len() ..], &[0xff]);
+    }
+
+    //decoded payloads borrow straight from the buffer
+    let mut r = Reader::init(&bytes);
+    let decoded = Message::read(&mut r).unwrap();
+    assert_eq!(decoded, msg);
+    assert!(!r.any_left());
+    match decoded {
+        Message::Hello(ref h) => match h.random.0 {
+            Cow::Borrowed(_) => {},
+            Cow::Owned(_) => panic!("payload was copied"),
+        },
+        _ => panic!("wrong variant"),
+    }
+
+    let bye = Message::Bye { reason: PayloadU8::from_slice(b"done") };
+    assert_eq!(Message::read(&mut Reader::init(&bye.get_encoding())).unwrap(), bye);
+    assert_eq!(Message::Ping.get_encoding(), vec![0xff]);
+
+    //unknown tags are rejected at the tag's offset
+    let bad = [7u8];
+    assert_eq!(Message::read(&mut Reader::init(&bad)),
+        Err(DecodeError::InvalidValue { offset: 0, what: "enum tag" }));
+
+    //errors are labelled with the path to the field that failed
+    let mut bad = bytes.clone();
+    bad[11] = 9;
+    let err = Reader::init(&bad).context("msg", Message::read).unwrap_err();
+    assert_eq!(err.path().unwrap().to_string(), "msg.Hello.0.extensions[0].body");
+    assert_eq!(err.root(), &DecodeError::Truncated { offset: 12, needed: 9, available: 6 });
+
+    //owned messages outlive the buffer they were decoded from
+    let owned: Message<'static> = Message::decode_owned(&bytes.clone()).unwrap();
+    assert_eq!(owned, msg);
+    match owned {
+        Message::Hello(ref h) => assert!(matches!(h.extensions[0].body.0, Cow::Owned(_))),
+        _ => panic!("wrong variant"),
+    }
+    assert_eq!(Message::Ping.into_owned(), Message::Ping);
+
+    //type parameters become their owned form
+    #[derive(Debug,PartialEq,IntoOwned)]
+    struct Pair<T, const N: usize>(T, [u8; N]);
+    let pair: Pair<PayloadU8<'static>, 2> = Pair(PayloadU8::from_slice(&bytes[2 .. 4]), [1, 2]).into_owned();
+    assert_eq!(pair.0.to_slice(), b"ra");
+}
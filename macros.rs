@@ -1,7 +1,169 @@
-/// A macro which takes an Option<T> and returns None if it
-/// is None, otherwise unwraps().
-#[export_macro]
-macro_rules! try_ret(
-    ($e:expr) => (match $e { Some(e) => e, None => return None })
-);
+/// Build a code-point enum, as used for cipher suites, extension types
+/// and alert codes. Each named variant maps to a fixed value, anything
+/// else is kept as `Unknown` so it re-encodes unchanged.
+///
+/// ```ignore
+/// codepoint_enum! {
+///   pub enum AlertLevel: u8 {
+///     Warning => 0x01,
+///     Fatal => 0x02
+///   }
+/// }
+/// ```
+///
+/// The raw type may be `u8` or `u16`. `From` conversions are generated
+/// in both directions along with `Codec`.
+#[macro_export]
+macro_rules! codepoint_enum {
+  (
+    $(#[$meta:meta])*
+    $vis:vis enum $name:ident: u8 {
+      $( $(#[$vmeta:meta])* $var:ident => $val:literal ),* $(,)?
+    }
+  ) => (
+    $crate::codepoint_enum!(@build
+      $(#[$meta])* $vis $name, u8, encode_u8, read_u8,
+      $( $(#[$vmeta])* $var => $val ),*
+    );
+  );
+  (
+    $(#[$meta:meta])*
+    $vis:vis enum $name:ident: u16 {
+      $( $(#[$vmeta:meta])* $var:ident => $val:literal ),* $(,)?
+    }
+  ) => (
+    $crate::codepoint_enum!(@build
+      $(#[$meta])* $vis $name, u16, encode_u16, read_u16_be,
+      $( $(#[$vmeta])* $var => $val ),*
+    );
+  );
+  (@build
+    $(#[$meta:meta])* $vis:vis $name:ident, $raw:ident, $encode:ident, $read:ident,
+    $( $(#[$vmeta:meta])* $var:ident => $val:literal ),*
+  ) => (
+    $(#[$meta])*
+    #[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+    $vis enum $name {
+      $( $(#[$vmeta])* $var, )*
+      ///A value with no named variant, kept as received
+      Unknown($raw)
+    }
+    impl From<$raw> for $name {
+      fn from(x: $raw) -> Self {
+        match x {
+          $( $val => $name::$var, )*
+          x => $name::Unknown(x)
+        }
+      }
+    }
+    impl From<$name> for $raw {
+      fn from(x: $name) -> Self {
+        match x {
+          $( $name::$var => $val, )*
+          $name::Unknown(x) => x
+        }
+      }
+    }
+    $crate::__if_alloc! {
+      impl<'a> $crate::Codec<'a> for $name {
+        const FIXED_LEN: ::core::option::Option<usize> =
+          ::core::option::Option::Some(::core::mem::size_of::<$raw>());
+        fn encode(&self, bytes: &mut $crate::__private::Vec<u8>) {
+          $crate::$encode($raw::from(*self), bytes);
+        }
+        fn try_encoded_len(&self) -> ::core::result::Result<usize,$crate::EncodeError> {
+          ::core::result::Result::Ok(::core::mem::size_of::<$raw>())
+        }
+        $crate::__if_bytes! {
+          fn encode_buf<B: $crate::__private::BufMut>(&self, buf: &mut B) {
+            buf.put_slice(&$raw::from(*self).to_be_bytes());
+          }
+        }
+        fn read(r: &mut $crate::Reader<'a>) -> ::core::result::Result<Self,$crate::DecodeError> {
+          r.$read().map(|x| $name::from(x as $raw))
+        }
+      }
+      impl $crate::IntoOwned for $name {
+        type Owned = $name;
+        #[inline(always)]
+        fn into_owned(self) -> $name {
+          self
+        }
+      }
+    }
+  );
+}
 
+//Expands its input only when codecbase was built with `alloc`, so
+//exported macros can emit `Codec` impls without the caller
+//knowing which features are on.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_alloc {
+  ($($t:tt)*) => ($($t)*)
+}
+#[cfg(not(feature = "alloc"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_alloc {
+  ($($t:tt)*) => ()
+}
+
+//The same for `bytes`, for the `BufMut` encoders
+#[cfg(feature = "bytes")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_bytes {
+  ($($t:tt)*) => ($($t)*)
+}
+#[cfg(not(feature = "bytes"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __if_bytes {
+  ($($t:tt)*) => ()
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_codepoint_enum() {
+  use alloc::vec::Vec;
+  use super::{Codec,Reader};
+
+  codepoint_enum! {
+    enum AlertLevel: u8 {
+      Warning => 0x01,
+      Fatal => 0x02,
+    }
+  }
+  codepoint_enum! {
+    enum ExtensionType: u16 {
+      ServerName => 0x0000,
+      /// RFC 7301
+      ALProtocolNegotiation => 0x0010
+    }
+  }
+
+  assert_eq!(AlertLevel::from(2u8), AlertLevel::Fatal);
+  assert_eq!(u8::from(AlertLevel::Warning), 1);
+  assert_eq!(AlertLevel::from(9u8), AlertLevel::Unknown(9));
+  assert_eq!(AlertLevel::Fatal.get_encoding(), vec![2]);
+
+  //unknown values survive a decode/encode round trip
+  let bytes = [0x00u8,0x10, 0xfe,0x01, 0x00,0x00];
+  let mut r = Reader::init(&bytes);
+  let a = ExtensionType::read(&mut r).unwrap();
+  let b = ExtensionType::read(&mut r).unwrap();
+  let c = ExtensionType::read(&mut r).unwrap();
+  assert_eq!(a, ExtensionType::ALProtocolNegotiation);
+  assert_eq!(b, ExtensionType::Unknown(0xfe01));
+  assert_eq!(c, ExtensionType::ServerName);
+  let mut out = Vec::new();
+  for x in &[a,b,c] {
+    x.encode(&mut out);
+  }
+  assert_eq!(&out[..], &bytes[..]);
+  let raw: u16 = b.into();
+  assert_eq!(raw, 0xfe01);
+  assert!(ExtensionType::read(&mut r).is_err());
+}
@@ -0,0 +1,177 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::codec::{Codec, DecodeLimits, Payload, Reader};
+use super::error::DecodeError;
+
+///Conversion of a decoded value which borrows its input into one
+///which owns everything, so it can outlive the buffer or be moved
+///to another task. Borrowed payloads are copied, anything already
+///owned is moved.
+///
+///`#[derive(IntoOwned)]` implements it for structs and enums whose
+///fields all implement it, replacing every lifetime with `'static`.
+pub trait IntoOwned {
+  type Owned: 'static;
+
+  fn into_owned(self) -> Self::Owned;
+}
+
+///Decode straight into `'static` values, for input in a temporary
+///buffer. Implemented for every `Codec` which is also `IntoOwned`.
+///
+///```ignore
+///let hello: Hello<'static> = Hello::decode_owned(&buf)?;
+///drop(buf);
+///```
+pub trait DecodeOwned<'a>: Codec<'a> + IntoOwned {
+
+  ///Decode all of `bytes` like `read_exact`, and make the result owned
+  fn decode_owned(bytes: &'a [u8]) -> Result<Self::Owned,DecodeError> {
+    Self::read_exact(bytes).map(IntoOwned::into_owned)
+  }
+
+  ///`decode_owned` for untrusted input, enforcing `limits`
+  fn decode_owned_with_limits(bytes: &'a [u8], limits: DecodeLimits) -> Result<Self::Owned,DecodeError> {
+    Self::read_exact_with_limits(bytes, limits).map(IntoOwned::into_owned)
+  }
+
+  ///Decode one value from the front of `r` and make it owned
+  fn read_owned(r: &mut Reader<'a>) -> Result<Self::Owned,DecodeError> {
+    Self::read(r).map(IntoOwned::into_owned)
+  }
+}
+
+impl<'a, T: Codec<'a> + IntoOwned> DecodeOwned<'a> for T { }
+
+//Types which never borrow are their own owned form.
+macro_rules! ownedself {
+  ($($t: ty),*) => ($(
+    impl IntoOwned for $t {
+      type Owned = $t;
+      #[inline(always)]
+      fn into_owned(self) -> $t {
+        self
+      }
+    }
+  )*)
+}
+ownedself!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, usize, isize, bool, (), alloc::string::String);
+
+impl<'a> IntoOwned for Cow<'a, [u8]> {
+  type Owned = Cow<'static, [u8]>;
+  fn into_owned(self) -> Cow<'static, [u8]> {
+    Cow::Owned(Cow::into_owned(self))
+  }
+}
+
+impl<'a> IntoOwned for Cow<'a, str> {
+  type Owned = Cow<'static, str>;
+  fn into_owned(self) -> Cow<'static, str> {
+    Cow::Owned(Cow::into_owned(self))
+  }
+}
+
+impl<'a> IntoOwned for Payload<'a> {
+  type Owned = Payload<'static>;
+  fn into_owned(self) -> Self::Owned {
+    Payload(IntoOwned::into_owned(self.0))
+  }
+}
+
+impl<T: IntoOwned> IntoOwned for Vec<T> {
+  type Owned = Vec<T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    self.into_iter().map(IntoOwned::into_owned).collect()
+  }
+}
+
+impl<T: IntoOwned> IntoOwned for Option<T> {
+  type Owned = Option<T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    self.map(IntoOwned::into_owned)
+  }
+}
+
+impl<T: IntoOwned> IntoOwned for Box<T> {
+  type Owned = Box<T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    Box::new((*self).into_owned())
+  }
+}
+
+impl<T: IntoOwned, const N: usize> IntoOwned for [T; N] {
+  type Owned = [T::Owned; N];
+  fn into_owned(self) -> Self::Owned {
+    self.map(IntoOwned::into_owned)
+  }
+}
+
+macro_rules! tupleowned {
+  ($($name: ident)+) => (
+    impl<$($name: IntoOwned),+> IntoOwned for ($($name,)+) {
+      type Owned = ($($name::Owned,)+);
+      #[allow(non_snake_case)]
+      fn into_owned(self) -> Self::Owned {
+        let ($($name,)+) = self;
+        ($($name.into_owned(),)+)
+      }
+    }
+  )
+}
+tupleowned!(A);
+tupleowned!(A B);
+tupleowned!(A B C);
+tupleowned!(A B C D);
+tupleowned!(A B C D E);
+tupleowned!(A B C D E F);
+tupleowned!(A B C D E F G);
+tupleowned!(A B C D E F G H);
+tupleowned!(A B C D E F G H I);
+tupleowned!(A B C D E F G H I J);
+tupleowned!(A B C D E F G H I J K);
+tupleowned!(A B C D E F G H I J K L);
+
+#[test]
+fn test_into_owned() {
+  use super::codec::{PayloadU8, PayloadU16, read_vec_u8};
+  use super::prefix::{LengthPrefixed, U8};
+  use super::string::StrU8;
+
+  type Entry<'a> = (u16, PayloadU8<'a>, StrU8<'a>);
+
+  fn decode(buf: Vec<u8>) -> (Entry<'static>, PayloadU16<'static>) {
+    //the buffer is dropped at the end, the results outlive it
+    let entry = Entry::decode_owned(&buf[.. 9]).unwrap();
+    let mut r = Reader::init(&buf[9 ..]);
+    let p = PayloadU16::read_owned(&mut r).unwrap();
+    (entry, p)
+  }
+
+  let buf = vec![0,7, 2,b'h',b'i', 3,b'a',b'b',b'c', 0,1, 0xee];
+  let (entry, p) = decode(buf);
+  assert_eq!(entry.0, 7);
+  assert_eq!(entry.1.to_slice(), b"hi");
+  assert!(matches!(entry.1.0, Cow::Owned(_)));
+  assert_eq!(entry.2.as_str(), "abc");
+  assert_eq!(p.to_slice(), &[0xee]);
+  assert_eq!(Some(p.clone()).into_owned(), Some(p));
+
+  //limits apply to untrusted input
+  let buf = [0,7, 2,b'h',b'i', 3,b'a',b'b',b'c'];
+  let limits = DecodeLimits { max_payload_len: 2, ..DecodeLimits::UNLIMITED };
+  assert_eq!(Entry::decode_owned_with_limits(&buf, limits),
+    Err(DecodeError::LimitExceeded { offset: 6, limit: "max_payload_len" }));
+
+  //vectors and nested wrappers are converted throughout
+  let bytes = [4, 3, 2, b'o', b'k'];
+  let v: Vec<LengthPrefixed<U8, PayloadU8>> = read_vec_u8(&mut Reader::init(&bytes)).unwrap();
+  let owned: Vec<LengthPrefixed<U8, PayloadU8<'static>>> = v.clone().into_owned();
+  assert_eq!(owned, v);
+  assert_eq!([1u8, 2].into_owned(), [1, 2]);
+
+  //tuples go up to twelve, as `Codec` does
+  let twelve = (1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, PayloadU8::from_slice(b"z"));
+  assert_eq!(twelve.clone().into_owned(), twelve);
+}
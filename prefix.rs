@@ -0,0 +1,461 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::{Deref,DerefMut};
+
+#[cfg(feature = "alloc")]
+use alloc::borrow::Cow;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::codec::{ByteOrder, Bytes, Reader, borrowed, put_uint, to_str};
+#[cfg(feature = "alloc")]
+use super::codec::{Codec, check_len};
+#[cfg(feature = "alloc")]
+use super::owned::IntoOwned;
+use super::varint::{QUIC_VARINT_MAX, leb128_len, put_leb128, quic_varint_len, put_quic_varint};
+use super::error::DecodeError;
+#[cfg(feature = "alloc")]
+use super::error::EncodeError;
+
+///How a length prefix is encoded. Implemented by the marker types
+///`U8` to `U64`, their little endian versions and the varints, for
+///use as the `L` of `LengthPrefixed`.
+pub trait LengthPrefix {
+  ///largest length the prefix can describe
+  const MAX: u64;
+
+  ///bytes every prefix takes, None when it depends on the length
+  const WIDTH: Option<usize>;
+
+  ///what `LengthPrefixed` values print as in `Debug`, the name of
+  ///the payload alias with this prefix
+  const DEBUG_NAME: &'static str;
+
+  ///Decode a length
+  fn read_len(r: &mut Reader) -> Result<usize,DecodeError>;
+
+  ///Number of bytes the prefix for `len` takes
+  fn prefix_len(len: usize) -> usize;
+
+  ///Write the prefix for `len` into `slot`, which is exactly
+  ///`prefix_len(len)` bytes long
+  fn put_len(len: usize, slot: &mut [u8]);
+
+  ///Append `len`, which is at most `MAX`
+  #[cfg(feature = "alloc")]
+  fn encode_len(len: usize, bytes: &mut Vec<u8>) {
+    let start = bytes.len();
+    bytes.resize(start + Self::prefix_len(len), 0);
+    Self::put_len(len, &mut bytes[start ..]);
+  }
+
+  ///Check a body of `len` bytes may be written after this prefix,
+  ///by default that `len` is at most `MAX`
+  #[cfg(feature = "alloc")]
+  fn check_body_len(len: usize) -> Result<(),EncodeError> {
+    check_len(len, Self::MAX)
+  }
+}
+
+macro_rules! lengthprefix {
+  ($(#[$doc: meta])* $name: ident, $len_max: expr, $read_len: ident, fixed $width: literal, $order: ident) => (
+    lengthprefix!(@build $(#[$doc])* $name, $len_max, $read_len, Some($width),
+      |_| $width, |len, slot| put_uint(len, slot, ByteOrder::$order));
+  );
+  ($(#[$doc: meta])* $name: ident, $len_max: expr, $read_len: ident, varint $prefix_len: ident, $put_len: ident) => (
+    lengthprefix!(@build $(#[$doc])* $name, $len_max, $read_len, None, $prefix_len, $put_len);
+  );
+  (@build
+    $(#[$doc: meta])* $name: ident, $len_max: expr, $read_len: ident, $width: expr,
+    $prefix_len: expr, $put_len: expr
+  ) => (
+    $(#[$doc])*
+    #[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+    pub struct $name;
+
+    impl LengthPrefix for $name {
+      const MAX: u64 = $len_max;
+      const WIDTH: Option<usize> = $width;
+      const DEBUG_NAME: &'static str = concat!("Payload", stringify!($name));
+
+      fn read_len(r: &mut Reader) -> Result<usize,DecodeError> {
+        r.$read_len()
+      }
+
+      fn prefix_len(len: usize) -> usize {
+        $prefix_len(len as u64)
+      }
+
+      fn put_len(len: usize, slot: &mut [u8]) {
+        $put_len(len as u64, slot)
+      }
+    }
+  );
+}
+
+lengthprefix!(
+  ///u8 length prefix
+  U8, 0xff, read_u8, fixed 1, BigEndian);
+lengthprefix!(
+  ///big endian u16 length prefix
+  U16, 0xffff, read_u16_be, fixed 2, BigEndian);
+lengthprefix!(
+  ///big endian u24 length prefix
+  U24, 0x00ff_ffff, read_u24_be, fixed 3, BigEndian);
+lengthprefix!(
+  ///big endian u32 length prefix
+  U32, 0xffff_ffff, read_u32_be, fixed 4, BigEndian);
+lengthprefix!(
+  ///big endian u64 length prefix
+  U64, u64::MAX, read_u64_be, fixed 8, BigEndian);
+lengthprefix!(
+  ///little endian u16 length prefix
+  U16Le, 0xffff, read_u16_le, fixed 2, LittleEndian);
+lengthprefix!(
+  ///little endian u24 length prefix
+  U24Le, 0x00ff_ffff, read_u24_le, fixed 3, LittleEndian);
+lengthprefix!(
+  ///little endian u32 length prefix
+  U32Le, 0xffff_ffff, read_u32_le, fixed 4, LittleEndian);
+lengthprefix!(
+  ///little endian u64 length prefix
+  U64Le, u64::MAX, read_u64_le, fixed 8, LittleEndian);
+lengthprefix!(
+  ///unsigned LEB128 length prefix
+  Varint, u64::MAX, read_leb128_len, varint leb128_len, put_leb128);
+lengthprefix!(
+  ///QUIC variable length integer prefix
+  QuicVarint, QUIC_VARINT_MAX, read_quic_varint_len, varint quic_varint_len, put_quic_varint);
+
+///Write `body` preceded by its `L` length. A fixed width prefix is
+///reserved and filled in afterwards, as `Writer` does, so the body
+///is never moved. A varint is written first when `len` knows the
+///length `body` writes, otherwise the body is written and moved up
+///behind its prefix, so it is only encoded once.
+#[cfg(feature = "alloc")]
+pub(crate) fn encode_prefixed<L, N, F>(bytes: &mut Vec<u8>, len: N, body: F)
+where L: LengthPrefix, N: FnOnce() -> Option<usize>, F: FnOnce(&mut Vec<u8>)
+{
+  let start = bytes.len();
+  match (L::WIDTH, len()) {
+    (Some(width), _) => {
+      bytes.resize(start + width, 0);
+      body(bytes);
+      let len = bytes.len() - start - width;
+      debug_check_len::<L>(len);
+      L::put_len(len, &mut bytes[start .. start + width]);
+    }
+    (None, Some(len)) => {
+      debug_check_len::<L>(len);
+      L::encode_len(len, bytes);
+      let prefix = bytes.len() - start;
+      body(bytes);
+      debug_assert_eq!(bytes.len() - start - prefix, len, "body length disagrees with exact_len");
+    }
+    (None, None) => {
+      body(bytes);
+      debug_check_len::<L>(bytes.len() - start);
+      insert_prefix::<L>(bytes, start);
+    }
+  }
+}
+
+///Debug builds check the body `encode` wrote is allowed by `L`,
+///as `Writer` does for its prefixes
+#[cfg(feature = "alloc")]
+#[inline(always)]
+fn debug_check_len<L: LengthPrefix>(len: usize) {
+  debug_assert!(L::check_body_len(len).is_ok(), "{} body of {} bytes is not allowed by its length prefix",
+    L::DEBUG_NAME, len);
+}
+
+///Put the `L` prefix for everything after `start` in front of it
+#[cfg(feature = "alloc")]
+fn insert_prefix<L: LengthPrefix>(bytes: &mut Vec<u8>, start: usize) {
+  let len = bytes.len() - start;
+  L::encode_len(len, bytes);
+  let prefix = bytes.len() - start - len;
+  bytes[start ..].rotate_right(prefix);
+}
+
+///Write the `L` prefix for `len` into `buf`
+#[cfg(feature = "bytes")]
+pub(crate) fn put_prefix<L: LengthPrefix, B: ::bytes::BufMut>(len: usize, buf: &mut B) {
+  //a u64 as LEB128 is the longest prefix
+  let mut slot = [0u8; 10];
+  let slot = &mut slot[.. L::prefix_len(len)];
+  L::put_len(len, slot);
+  buf.put_slice(slot);
+}
+
+///Checked version of `encode_prefixed`, the body length must pass
+///`L::check_body_len`. On error `bytes` is truncated back to where
+///it was.
+#[cfg(feature = "alloc")]
+pub(crate) fn try_encode_prefixed<L, N, F>(bytes: &mut Vec<u8>, len: N, body: F) -> Result<(),EncodeError>
+where
+  L: LengthPrefix,
+  N: FnOnce() -> Option<usize>,
+  F: FnOnce(&mut Vec<u8>) -> Result<(),EncodeError>
+{
+  let start = bytes.len();
+  let ret = match (L::WIDTH, len()) {
+    (Some(width), _) => {
+      bytes.resize(start + width, 0);
+      body(bytes).and_then(|()| {
+        let len = bytes.len() - start - width;
+        L::check_body_len(len)?;
+        L::put_len(len, &mut bytes[start .. start + width]);
+        Ok(())
+      })
+    }
+    //the length is known up front, so check it before writing
+    (None, Some(len)) => {
+      L::check_body_len(len).and_then(|()| {
+        L::encode_len(len, bytes);
+        body(bytes)
+      })
+    }
+    (None, None) => {
+      body(bytes).and_then(|()| {
+        L::check_body_len(bytes.len() - start)?;
+        insert_prefix::<L>(bytes, start);
+        Ok(())
+      })
+    }
+  };
+  if ret.is_err() {
+    bytes.truncate(start);
+  }
+  ret
+}
+
+///A `T` preceded by the length of its encoding, with the prefix
+///described by `L`. Any `Codec` can be wrapped, including structs,
+///and `T` is decoded from a reader over exactly that length. Unlike
+///vectors, prefixed values do not count towards `max_depth`.
+///
+///The payload types are `LengthPrefixed` over raw `Bytes`:
+///
+///```ignore
+///type Extension<'a> = (u16, LengthPrefixed<U16, ServerName<'a>>);
+///let host: PayloadU8 = PayloadU8::from_slice(b"example.com");
+///```
+///
+///They used to be tuple structs of their own. Being aliases they
+///can no longer be built or matched as `PayloadU8(cow)`: build
+///them with `new`, `from_slice` or `From`, and reach the bytes
+///through `.0` as before. `Debug` output is unchanged.
+pub struct LengthPrefixed<L, T>(pub T, PhantomData<fn() -> L>);
+
+impl<L, T> LengthPrefixed<L, T> {
+
+  ///Wrap `value`, its length is worked out when encoding
+  pub fn wrap(value: T) -> Self {
+    LengthPrefixed(value, PhantomData)
+  }
+
+  pub fn into_inner(self) -> T {
+    self.0
+  }
+}
+
+impl<L, T> Deref for LengthPrefixed<L, T> {
+  type Target = T;
+  fn deref(&self) -> &T {
+    &self.0
+  }
+}
+
+impl<L, T> DerefMut for LengthPrefixed<L, T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.0
+  }
+}
+
+impl<L, T: Clone> Clone for LengthPrefixed<L, T> {
+  fn clone(&self) -> Self {
+    LengthPrefixed::wrap(self.0.clone())
+  }
+}
+
+impl<L, T: PartialEq> PartialEq for LengthPrefixed<L, T> {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+impl<L, T: Eq> Eq for LengthPrefixed<L, T> { }
+
+//prints as the payload tuple structs the aliases replaced did
+impl<L: LengthPrefix, T: fmt::Debug> fmt::Debug for LengthPrefixed<L, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_tuple(L::DEBUG_NAME).field(&self.0).finish()
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a, L: LengthPrefix, T: Codec<'a>> Codec<'a> for LengthPrefixed<L, T> {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    encode_prefixed::<L, _, _>(bytes, || self.0.exact_len(), |bytes| self.0.encode(bytes));
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    try_encode_prefixed::<L, _, _>(bytes, || self.0.exact_len(), |bytes| self.0.try_encode(bytes))
+  }
+  fn exact_len(&self) -> Option<usize> {
+    self.0.exact_len().map(|len| L::prefix_len(len) + len)
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    let len = self.0.try_encoded_len()?;
+    L::check_body_len(len)?;
+    Ok(L::prefix_len(len) + len)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: ::bytes::BufMut>(&self, buf: &mut B) {
+    match self.0.exact_len() {
+      Some(len) => {
+        put_prefix::<L, _>(len, buf);
+        self.0.encode_buf(buf);
+      }
+      //finding the length means encoding it, so keep that encoding
+      None => buf.put_slice(&self.get_encoding())
+    }
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let len = L::read_len(r)?;
+    let mut body = r.window(len)?;
+    let value = T::read(&mut body)?;
+    r.end_sub(body)?;
+    Ok(LengthPrefixed::wrap(value))
+  }
+}
+
+#[cfg(feature = "alloc")]
+impl<L: 'static, T: IntoOwned> IntoOwned for LengthPrefixed<L, T> {
+  type Owned = LengthPrefixed<L, T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    LengthPrefixed::wrap(self.0.into_owned())
+  }
+}
+
+//without alloc `Bytes` is already a reference
+#[cfg_attr(not(feature = "alloc"), allow(clippy::needless_borrow))]
+impl<'a, L> LengthPrefixed<L, Bytes<'a>> {
+  #[cfg(feature = "alloc")]
+  pub fn new(bytes: Vec<u8>) -> Self {
+    LengthPrefixed::wrap(Cow::Owned(bytes))
+  }
+  pub fn from_slice(data: &'a [u8]) -> Self {
+    LengthPrefixed::wrap(borrowed(data))
+  }
+  #[inline(always)]
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+  #[inline(always)]
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+  pub fn to_str(&self) -> Option<&str> {
+    to_str(&self.0)
+  }
+  pub fn to_slice(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl<'a, L> From<&'a [u8]> for LengthPrefixed<L, Bytes<'a>> {
+  fn from(x: &'a [u8]) -> Self {
+    Self::from_slice(x)
+  }
+}
+#[cfg(feature = "alloc")]
+impl<'a, L> From<Cow<'a, [u8]>> for LengthPrefixed<L, Bytes<'a>> {
+  fn from(x: Cow<'a, [u8]>) -> Self {
+    LengthPrefixed::wrap(x)
+  }
+}
+#[cfg(feature = "alloc")]
+impl<'a, L> From<Vec<u8>> for LengthPrefixed<L, Bytes<'a>> {
+  fn from(x: Vec<u8>) -> Self {
+    Self::new(x)
+  }
+}
+#[cfg(feature = "alloc")]
+impl<'a, L> From<LengthPrefixed<L, Bytes<'a>>> for Vec<u8> {
+  fn from(x: LengthPrefixed<L, Bytes<'a>>) -> Self {
+    x.0.into_owned()
+  }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_length_prefixed() {
+  use super::codec::{PayloadU8, PayloadU16, PayloadVarint};
+
+  #[derive(Debug,PartialEq,Clone)]
+  struct Point {
+    x: u16,
+    y: u16
+  }
+  impl<'a> Codec<'a> for Point {
+    fn encode(&self, bytes: &mut Vec<u8>) {
+      self.x.encode(bytes);
+      self.y.encode(bytes);
+    }
+    fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+      Ok(Point { x: u16::read(r)?, y: u16::read(r)? })
+    }
+  }
+
+  //structs and nested prefixes
+  let p = LengthPrefixed::<U8, Point>::wrap(Point { x: 1, y: 2 });
+  assert_eq!(p.get_encoding(), vec![4, 0,1, 0,2]);
+  assert_eq!(p.x, 1);
+  let nested = LengthPrefixed::<U16, _>::wrap((7u8, p.clone()));
+  let enc = nested.get_encoding();
+  assert_eq!(enc, vec![0,6, 7, 4, 0,1, 0,2]);
+  let back = LengthPrefixed::<U16, (u8, LengthPrefixed<U8, Point>)>::read_exact(&enc).unwrap();
+  assert_eq!(back, nested);
+  assert_eq!(back.into_inner().1.into_inner(), Point { x: 1, y: 2 });
+
+  //the value must fit inside its prefix
+  let err = LengthPrefixed::<U8, Point>::read_exact(&[3, 0,1, 0,2]).unwrap_err();
+  assert_eq!(err.root(), &DecodeError::Truncated { offset: 3, needed: 2, available: 1 });
+  let mut r = Reader::init(&[5, 0,1, 0,2, 9, 0xee]);
+  r.set_strict(true);
+  assert_eq!(LengthPrefixed::<U8, Point>::read(&mut r),
+    Err(DecodeError::TrailingData { offset: 5, left: 1 }));
+
+  //varint prefixes, and overflow leaves the buffer untouched
+  let v = LengthPrefixed::<Varint, _>::wrap(PayloadU16::new(vec![0u8; 200]));
+  let enc = v.get_encoding();
+  assert_eq!(&enc[.. 4], &[202, 1, 0, 200]);
+  assert_eq!(LengthPrefixed::<Varint, PayloadU16>::read_exact(&enc), Ok(v));
+  let mut bytes = vec![0xaa];
+  let big = LengthPrefixed::<U8, _>::wrap(PayloadU8::new(vec![0u8; 255]));
+  assert_eq!(big.try_encode(&mut bytes), Err(EncodeError::LengthOverflow { max: 0xff, actual: 256 }));
+  assert_eq!(bytes, vec![0xaa]);
+
+  //fixed prefixes are filled in after the body, varints first
+  let q = LengthPrefixed::<QuicVarint, _>::wrap(PayloadU8::new(vec![1u8; 100]));
+  let enc = q.get_encoding();
+  assert_eq!(&enc[.. 3], &[0x40, 101, 100]);
+  assert_eq!(LengthPrefixed::<QuicVarint, PayloadU8>::read_exact(&enc), Ok(q));
+  assert_eq!(LengthPrefixed::<U16Le, _>::wrap(7u16).get_encoding(), vec![2, 0, 0, 7]);
+  let mut bytes = vec![0xaa];
+  let big = LengthPrefixed::<QuicVarint, _>::wrap(PayloadU8::new(vec![0u8; 256]));
+  assert!(big.try_encode(&mut bytes).is_err());
+  assert_eq!(bytes, vec![0xaa]);
+
+  //the payload aliases keep their API
+  let p = PayloadVarint::from(vec![b'h', b'i']);
+  assert_eq!(p.to_str(), Some("hi"));
+  assert_eq!(p.to_slice(), b"hi");
+  assert_eq!(p.get_encoding(), vec![2, b'h', b'i']);
+  let v: Vec<u8> = p.into();
+  assert_eq!(v, b"hi".to_vec());
+  assert_eq!(PayloadU8::from(&b"x"[..]).len(), 1);
+  assert_eq!(PayloadU8::from(Cow::Borrowed(&b"hi"[..])).0, Cow::Borrowed(&b"hi"[..]));
+  assert_eq!(format!("{:?}", PayloadU8::from_slice(b"hi")), "PayloadU8([104, 105])");
+  assert_eq!(format!("{:?}", PayloadVarint::new(vec![])), "PayloadVarint([])");
+}
@@ -0,0 +1,269 @@
+use core::convert::TryInto;
+use core::mem::size_of;
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+
+use super::codec::{Codec, Reader, items_exact_len, try_items_len};
+use super::error::{DecodeError,EncodeError};
+
+//Integers are always big endian, like `Reader::read_u*_be`, whatever
+//order the reader is set to, as `encode` has no order to follow.
+//Use `Reader::read_u*` and `Writer` for formats in another order.
+macro_rules! intcodec {
+  ($($t: ty),*) => ($(
+    impl<'a> Codec<'a> for $t {
+      const FIXED_LEN: Option<usize> = Some(size_of::<$t>());
+      fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_be_bytes());
+      }
+      fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+        Ok(size_of::<$t>())
+      }
+      #[cfg(feature = "bytes")]
+      fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+        buf.put_slice(&self.to_be_bytes());
+      }
+      fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+        let b = r.take(size_of::<$t>())?;
+        Ok(<$t>::from_be_bytes(b.try_into().unwrap()))
+      }
+    }
+  )*)
+}
+intcodec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+///A single byte which must be 0 or 1
+impl<'a> Codec<'a> for bool {
+  const FIXED_LEN: Option<usize> = Some(1);
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    bytes.push(*self as u8);
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    Ok(1)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    buf.put_u8(*self as u8);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let err = r.invalid("bool");
+    match r.read_u8()? {
+      0 => Ok(false),
+      1 => Ok(true),
+      _ => Err(err)
+    }
+  }
+}
+
+impl<'a> Codec<'a> for () {
+  const FIXED_LEN: Option<usize> = Some(0);
+  fn encode(&self, _: &mut Vec<u8>) { }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    Ok(0)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, _: &mut B) { }
+  fn read(_: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    Ok(())
+  }
+}
+
+///Fixed length arrays have no length prefix, each element
+///is encoded in turn. `[u8; N]` is a plain run of N bytes.
+impl<'a, T: Codec<'a>, const N: usize> Codec<'a> for [T; N] {
+  const FIXED_LEN: Option<usize> = match T::FIXED_LEN {
+    Some(len) => Some(len * N),
+    None => None
+  };
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    for i in self.iter() {
+      i.encode(bytes);
+    }
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    for i in self.iter() {
+      i.try_encode(bytes)?;
+    }
+    Ok(())
+  }
+  fn exact_len(&self) -> Option<usize> {
+    items_exact_len(self)
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    try_items_len(self)
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    for i in self.iter() {
+      i.encode_buf(buf);
+    }
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    //elements of a fixed length are taken as one run, which is
+    //traced once as bytes, and read from an untraced reader over it
+    let mut run = match Self::FIXED_LEN {
+      Some(len) => {
+        let settings = r.settings();
+        let mut run = Reader::with_settings(r.take(len)?, settings);
+        run.trace = None;
+        Some(run)
+      }
+      None => None
+    };
+    let from = match run {
+      Some(ref mut run) => run,
+      None => &mut *r
+    };
+    //built in place, without a Vec on the heap
+    let mut err = None;
+    let items: [Option<T>; N] = core::array::from_fn(|_| {
+      if err.is_some() {
+        return None;
+      }
+      T::read(from).map_err(|e| err = Some(e)).ok()
+    });
+    if let Some(ref run) = run {
+      r.reclaim_alloc(run);
+    }
+    match err {
+      Some(e) => Err(e),
+      None => Ok(items.map(|x| x.unwrap()))
+    }
+  }
+}
+
+///Sum of two `FIXED_LEN`s, None unless both are fixed
+const fn fixed_sum(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+  match (a, b) {
+    (Some(a), Some(b)) => Some(a + b),
+    _ => None
+  }
+}
+
+//Tuples encode their members in order.
+macro_rules! tuplecodec {
+  ($($name: ident)+) => (
+    impl<'a, $($name: Codec<'a>),+> Codec<'a> for ($($name,)+) {
+      const FIXED_LEN: Option<usize> = {
+        let len = Some(0);
+        $( let len = fixed_sum(len, $name::FIXED_LEN); )+
+        len
+      };
+      #[allow(non_snake_case)]
+      fn encode(&self, bytes: &mut Vec<u8>) {
+        let ($(ref $name,)+) = *self;
+        $( $name.encode(bytes); )+
+      }
+      #[allow(non_snake_case)]
+      fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+        let ($(ref $name,)+) = *self;
+        $( $name.try_encode(bytes)?; )+
+        Ok(())
+      }
+      #[allow(non_snake_case)]
+      fn exact_len(&self) -> Option<usize> {
+        let ($(ref $name,)+) = *self;
+        Some(0 $( + $name.exact_len()? )+)
+      }
+      #[allow(non_snake_case)]
+      fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+        let ($(ref $name,)+) = *self;
+        Ok(0 $( + $name.try_encoded_len()? )+)
+      }
+      #[cfg(feature = "bytes")]
+      #[allow(non_snake_case)]
+      fn encode_buf<BUF: BufMut>(&self, buf: &mut BUF) {
+        let ($(ref $name,)+) = *self;
+        $( $name.encode_buf(buf); )+
+      }
+      fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+        Ok(($($name::read(r)?,)+))
+      }
+    }
+  )
+}
+tuplecodec!(A);
+tuplecodec!(A B);
+tuplecodec!(A B C);
+tuplecodec!(A B C D);
+tuplecodec!(A B C D E);
+tuplecodec!(A B C D E F);
+tuplecodec!(A B C D E F G);
+tuplecodec!(A B C D E F G H);
+tuplecodec!(A B C D E F G H I);
+tuplecodec!(A B C D E F G H I J);
+tuplecodec!(A B C D E F G H I J K);
+tuplecodec!(A B C D E F G H I J K L);
+
+#[test]
+fn test_integers() {
+  let mut bytes = Vec::new();
+  0x01u8.encode(&mut bytes);
+  0x0203u16.encode(&mut bytes);
+  0x04050607u32.encode(&mut bytes);
+  (-2i16).encode(&mut bytes);
+  1u128.encode(&mut bytes);
+  assert_eq!(bytes.len(), 1 + 2 + 4 + 2 + 16);
+  assert_eq!(&bytes[..9], &[1,2,3,4,5,6,7,0xff,0xfe]);
+
+  let mut r = Reader::init(&bytes);
+  assert_eq!(u8::read(&mut r), Ok(1));
+  assert_eq!(u16::read(&mut r), Ok(0x0203));
+  assert_eq!(u32::read(&mut r), Ok(0x04050607));
+  assert_eq!(i16::read(&mut r), Ok(-2));
+  assert_eq!(u128::read(&mut r), Ok(1));
+  assert!(!r.any_left());
+  assert_eq!(u64::read(&mut r),
+    Err(DecodeError::Truncated { offset: 25, needed: 8, available: 0 }));
+}
+
+#[test]
+fn test_bool_unit_arrays_tuples() {
+  let bytes = [1u8, 0, 2];
+  let mut r = Reader::init(&bytes);
+  assert_eq!(bool::read(&mut r), Ok(true));
+  assert_eq!(bool::read(&mut r), Ok(false));
+  assert_eq!(bool::read(&mut r), Err(DecodeError::InvalidValue { offset: 2, what: "bool" }));
+
+  assert!(().get_encoding().is_empty());
+
+  let random = [7u8; 32];
+  assert_eq!(random.get_encoding(), vec![7u8; 32]);
+  let words: [u16; 3] = [1, 2, 0x300];
+  let enc = words.get_encoding();
+  assert_eq!(enc, vec![0,1, 0,2, 3,0]);
+  assert_eq!(<[u16; 3]>::read(&mut Reader::init(&enc)), Ok(words));
+  assert_eq!(<[u16; 4]>::read(&mut Reader::init(&enc)),
+    Err(DecodeError::Truncated { offset: 0, needed: 8, available: 6 }));
+  assert_eq!(<[bool; 3]>::read(&mut Reader::init(&bytes)),
+    Err(DecodeError::InvalidValue { offset: 2, what: "bool" }));
+
+  //a fixed length array is traced as one run of bytes
+  let trace = super::dump::Trace::new();
+  let mut r = Reader::traced(&random, &trace);
+  assert_eq!(<[u8; 32]>::read(&mut r), Ok(random));
+  let nodes = trace.nodes();
+  assert_eq!(nodes.len(), 1);
+  assert_eq!((nodes[0].label.as_str(), nodes[0].len), ("bytes", 32));
+
+  //other elements are read one by one
+  use super::codec::PayloadU8;
+  let names = [PayloadU8::from_slice(b"a"), PayloadU8::from_slice(b"")];
+  let enc = names.get_encoding();
+  assert_eq!(<[PayloadU8; 2]>::read(&mut Reader::init(&enc)), Ok(names));
+  assert_eq!(<[PayloadU8; 2]>::read(&mut Reader::init(&enc[.. 2])),
+    Err(DecodeError::Truncated { offset: 2, needed: 1, available: 0 }));
+
+  let t = (1u8, true, 0x0102u16, [9u8; 2]);
+  let enc = t.get_encoding();
+  assert_eq!(enc, vec![1, 1, 1,2, 9,9]);
+  let mut r = Reader::init(&enc);
+  assert_eq!(<(u8, bool, u16, [u8; 2])>::read(&mut r), Ok(t));
+  assert!(!r.any_left());
+
+  let big = (1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8, 9u8, 10u8, 11u8, 12u16);
+  assert_eq!(big.get_encoding().len(), 13);
+}
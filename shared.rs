@@ -0,0 +1,274 @@
+use alloc::vec::Vec;
+
+use bytes::{Buf, BufMut, Bytes};
+
+use super::codec::{Codec, DecodeLimits, Reader, check_len, extend, items_exact_len, to_str};
+use super::codec::{try_encoded_len_vec_u8, try_encoded_len_vec_u16, try_encoded_len_vec_u24, try_encoded_len_vec_u32, try_encoded_len_vec_u64};
+use super::error::{DecodeError, EncodeError};
+use super::owned::IntoOwned;
+use super::prefix::{LengthPrefixed, U8, U16, U24, U32, U64};
+
+impl<'a> Reader<'a> {
+
+  ///Build a Reader over a `Bytes`. Shared payloads read from it,
+  ///or from any `sub` reader of it, point into the same allocation
+  ///instead of being copied.
+  pub fn shared(bytes: &'a Bytes) -> Reader<'a> {
+    let mut r = Reader::init(&bytes[..]);
+    r.shared = Some(bytes);
+    r
+  }
+
+  ///Take len amount of data as a `Bytes`. This is only a copy
+  ///when the reader was not built with `shared`.
+  pub fn take_shared(&mut self, len: usize) -> Result<Bytes,DecodeError> {
+    let shared = self.shared;
+    if shared.is_none() {
+      self.charge_alloc(len)?;
+    }
+    let data = self.take(len)?;
+    Ok(match shared {
+      Some(src) => src.slice_ref(data),
+      None => Bytes::copy_from_slice(data)
+    })
+  }
+}
+
+///Decode a `T` from the front of `src`, advancing past it only
+///on success. Shared payloads within `T` are not copied.
+pub fn decode_bytes<T>(src: &mut Bytes) -> Result<T,DecodeError>
+where T: for<'a> Codec<'a>
+{
+  decode_bytes_with_limits(src, DecodeLimits::UNLIMITED)
+}
+
+///`decode_bytes` for untrusted input, enforcing `limits`
+pub fn decode_bytes_with_limits<T>(src: &mut Bytes, limits: DecodeLimits) -> Result<T,DecodeError>
+where T: for<'a> Codec<'a>
+{
+  let (ret, used) = {
+    let mut r = Reader::shared(src);
+    r.set_limits(limits);
+    let ret = T::read(&mut r)?;
+    (ret, r.used())
+  };
+  src.advance(used);
+  Ok(ret)
+}
+
+///The rest of the reader, as the body of the shared payload types.
+///Points into the reader's `Bytes` when it was built with `shared`.
+impl<'a> Codec<'a> for Bytes {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    extend(self, bytes);
+  }
+  fn exact_len(&self) -> Option<usize> {
+    Some(self.len())
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    Ok(self.len())
+  }
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    buf.put_slice(self);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let len = r.left();
+    r.take_shared(len)
+  }
+}
+
+impl IntoOwned for Bytes {
+  type Owned = Bytes;
+  #[inline(always)]
+  fn into_owned(self) -> Bytes {
+    self
+  }
+}
+
+///Like the borrowed payloads, but backed by a refcounted `Bytes`
+///which can be cloned and sent between tasks without copying.
+impl<L> LengthPrefixed<L, Bytes> {
+  pub fn new(bytes: Bytes) -> Self {
+    LengthPrefixed::wrap(bytes)
+  }
+  pub fn from_slice(data: &[u8]) -> Self {
+    LengthPrefixed::wrap(Bytes::copy_from_slice(data))
+  }
+  #[inline(always)]
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+  #[inline(always)]
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+  pub fn to_str(&self) -> Option<&str> {
+    to_str(&self.0)
+  }
+  pub fn to_slice(&self) -> &[u8] {
+    &self.0
+  }
+}
+
+impl<L> From<Bytes> for LengthPrefixed<L, Bytes> {
+  fn from(x: Bytes) -> Self {
+    Self::new(x)
+  }
+}
+impl<L> From<Vec<u8>> for LengthPrefixed<L, Bytes> {
+  fn from(x: Vec<u8>) -> Self {
+    Self::new(Bytes::from(x))
+  }
+}
+impl<L> From<LengthPrefixed<L, Bytes>> for Bytes {
+  fn from(x: LengthPrefixed<L, Bytes>) -> Self {
+    x.0
+  }
+}
+
+///Shared bytes with a u8 length prefix
+pub type SharedPayloadU8 = LengthPrefixed<U8, Bytes>;
+///Shared bytes with a big endian u16 length prefix
+pub type SharedPayloadU16 = LengthPrefixed<U16, Bytes>;
+///Shared bytes with a big endian u24 length prefix
+pub type SharedPayloadU24 = LengthPrefixed<U24, Bytes>;
+///Shared bytes with a big endian u32 length prefix
+pub type SharedPayloadU32 = LengthPrefixed<U32, Bytes>;
+///Shared bytes with a big endian u64 length prefix
+pub type SharedPayloadU64 = LengthPrefixed<U64, Bytes>;
+
+//When the items know their lengths the prefix is written first and
+//each item is encoded straight into the buffer. Otherwise they are
+//encoded once into a `Vec`, which gives the length.
+macro_rules! bufvec {
+  ($encode_buf: ident, $try_encode_buf: ident, $try_encoded_len: ident, $width: expr, $max: expr) => (
+    ///Like the `Vec` encoder of the same width, writing into any `BufMut`
+    pub fn $encode_buf<'a, B: BufMut, T: Codec<'a>>(buf: &mut B, items: &[T]) {
+      match items_exact_len(items) {
+        Some(len) => {
+          buf.put_uint(len as u64, $width);
+          for i in items {
+            i.encode_buf(buf);
+          }
+        }
+        None => {
+          let mut body = Vec::new();
+          for i in items {
+            i.encode(&mut body);
+          }
+          buf.put_uint(body.len() as u64, $width);
+          buf.put_slice(&body);
+        }
+      }
+    }
+    ///Checked version, nothing is written on error
+    pub fn $try_encode_buf<'a, B: BufMut, T: Codec<'a>>(buf: &mut B, items: &[T]) -> Result<(),EncodeError> {
+      if items_exact_len(items).is_some() {
+        $try_encoded_len(items)?;
+        $encode_buf(buf, items);
+        return Ok(());
+      }
+      let mut body = Vec::new();
+      for i in items {
+        i.try_encode(&mut body)?;
+      }
+      check_len(body.len(), $max)?;
+      buf.put_uint(body.len() as u64, $width);
+      buf.put_slice(&body);
+      Ok(())
+    }
+  )
+}
+bufvec!(encode_vec_u8_buf, try_encode_vec_u8_buf, try_encoded_len_vec_u8, 1, 0xff);
+bufvec!(encode_vec_u16_buf, try_encode_vec_u16_buf, try_encoded_len_vec_u16, 2, 0xffff);
+bufvec!(encode_vec_u24_buf, try_encode_vec_u24_buf, try_encoded_len_vec_u24, 3, 0x00ff_ffff);
+bufvec!(encode_vec_u32_buf, try_encode_vec_u32_buf, try_encoded_len_vec_u32, 4, 0xffff_ffff);
+bufvec!(encode_vec_u64_buf, try_encode_vec_u64_buf, try_encoded_len_vec_u64, 8, u64::MAX);
+
+#[test]
+fn test_shared_payloads() {
+  let wire = Bytes::from(vec![0u8,3, b'a',b'b',b'c', 2, b'x',b'y', 0xff]);
+  let mut src = wire.clone();
+  let a: SharedPayloadU16 = decode_bytes(&mut src).unwrap();
+  let b: SharedPayloadU8 = decode_bytes(&mut src).unwrap();
+  assert_eq!(a.to_slice(), b"abc");
+  assert_eq!(b.to_str(), Some("xy"));
+  assert_eq!(&src[..], &[0xff]);
+
+  //the payloads point into the received buffer
+  assert_eq!(a.0.as_ptr(), wire[2 ..].as_ptr());
+  assert_eq!(b.0.as_ptr(), wire[6 ..].as_ptr());
+
+  //and stay shared through sub readers
+  let mut r = Reader::shared(&wire);
+  let mut sub = r.sub(5).unwrap();
+  let c = SharedPayloadU16::read(&mut sub).unwrap();
+  assert_eq!(c, a);
+  assert_eq!(c.0.as_ptr(), a.0.as_ptr());
+
+  //a plain reader copies
+  let d = SharedPayloadU16::read(&mut Reader::init(&wire)).unwrap();
+  assert_eq!(d, a);
+  assert_ne!(d.0.as_ptr(), a.0.as_ptr());
+
+  //a failed decode leaves the source alone
+  let mut short = wire.slice(.. 4);
+  assert!(decode_bytes::<SharedPayloadU16>(&mut short).is_err());
+  assert_eq!(short.len(), 4);
+  let mut src = wire.clone();
+  let limits = DecodeLimits { max_payload_len: 2, ..DecodeLimits::UNLIMITED };
+  assert_eq!(decode_bytes_with_limits::<SharedPayloadU16>(&mut src, limits),
+    Err(DecodeError::LimitExceeded { offset: 2, limit: "max_payload_len" }));
+  assert_eq!(src.len(), wire.len());
+
+  assert_eq!(a.get_encoding(), &wire[.. 5]);
+  let big = SharedPayloadU8::from(vec![0u8; 256]);
+  assert_eq!(big.try_get_encoding(),
+    Err(EncodeError::LengthOverflow { max: 0xff, actual: 256 }));
+}
+
+//an unchecked encode of a body too long for the prefix is caught
+//in debug builds rather than written with a truncated length
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "PayloadU8 body of 256 bytes is not allowed by its length prefix")]
+fn test_shared_encode_overflow() {
+  SharedPayloadU8::from(vec![0u8; 256]).get_encoding();
+}
+
+#[test]
+fn test_encode_buf() {
+  use bytes::BytesMut;
+
+  let mut buf = BytesMut::new();
+  0x0102u16.encode_buf(&mut buf);
+  encode_vec_u8_buf(&mut buf, &[SharedPayloadU8::from_slice(b"hi")]);
+  assert_eq!(&buf[..], &[1,2, 3, 2,b'h',b'i']);
+
+  let items = [SharedPayloadU8::from(vec![0u8; 255]), SharedPayloadU8::from_slice(b"")];
+  assert_eq!(try_encode_vec_u8_buf(&mut buf, &items),
+    Err(EncodeError::LengthOverflow { max: 0xff, actual: 257 }));
+  assert!(SharedPayloadU8::from(vec![0u8; 256]).try_encode_buf(&mut buf).is_err());
+  assert_eq!(buf.len(), 6);
+  try_encode_vec_u16_buf(&mut buf, &items).unwrap();
+  assert_eq!(&buf[6 .. 8], &[1, 1]);
+
+  //nested prefixes are written directly and match the Vec encoding
+  use super::{BoundedVec, CountedVecU8, LengthPrefixed, PayloadU8, QuicVarint, StrU8, U16};
+  let nested = (
+    LengthPrefixed::<QuicVarint, _>::wrap((7u8, PayloadU8::from_slice(b"abc"))),
+    BoundedVec::<U16, StrU8, 0, 16>::new(vec![StrU8::new("x").unwrap()]),
+    CountedVecU8::new(vec![true, false]),
+  );
+  let mut buf = BytesMut::new();
+  nested.try_encode_buf(&mut buf).unwrap();
+  assert_eq!(&buf[..], &nested.get_encoding()[..]);
+  assert_eq!(nested.try_encoded_len(), Ok(buf.len()));
+
+  //a bound broken deep inside is found before anything is written
+  let long = BoundedVec::<U16, StrU8, 0, 16>::new(vec![StrU8::new("0123456789abcdef").unwrap()]);
+  let bad = (1u8, long);
+  assert_eq!(bad.try_encode_buf(&mut buf),
+    Err(EncodeError::LengthOutOfRange { min: 0, max: 16, actual: 17 }));
+  assert_eq!(&buf[..], &nested.get_encoding()[..]);
+}
@@ -0,0 +1,106 @@
+use core::ops::{Deref, Range};
+
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+
+use super::codec::{Codec, Reader};
+use super::error::{DecodeError, EncodeError};
+use super::owned::IntoOwned;
+
+///A decoded value along with the range of bytes it was decoded
+///from, as offsets into the buffer of the outermost `Reader`.
+///Encodes exactly as `T` does.
+///
+///```ignore
+///let hello = Spanned::<ClientHello>::read(&mut r)?;
+///transcript.update(hello.bytes_in(&message));
+///```
+#[derive(Debug,Clone,PartialEq)]
+pub struct Spanned<T> {
+  pub value: T,
+  pub span: Range<usize>
+}
+
+impl<T> Spanned<T> {
+
+  ///Drop the span
+  pub fn into_inner(self) -> T {
+    self.value
+  }
+
+  ///The bytes `value` was decoded from, given the buffer the
+  ///outermost reader was built over
+  pub fn bytes_in<'b>(&self, root: &'b [u8]) -> &'b [u8] {
+    &root[self.span.clone()]
+  }
+}
+
+impl<T> Deref for Spanned<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.value
+  }
+}
+
+impl<'a, T: Codec<'a>> Codec<'a> for Spanned<T> {
+  const FIXED_LEN: Option<usize> = T::FIXED_LEN;
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    self.value.encode(bytes);
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    self.value.try_encode(bytes)
+  }
+  fn exact_len(&self) -> Option<usize> {
+    self.value.exact_len()
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    self.value.try_encoded_len()
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    self.value.encode_buf(buf);
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let start = r.absolute_offset();
+    let value = T::read(r)?;
+    Ok(Spanned { value, span: start .. r.absolute_offset() })
+  }
+}
+
+impl<T: IntoOwned> IntoOwned for Spanned<T> {
+  type Owned = Spanned<T::Owned>;
+  fn into_owned(self) -> Self::Owned {
+    Spanned { value: self.value.into_owned(), span: self.span }
+  }
+}
+
+#[test]
+fn test_spanned() {
+  use super::codec::{PayloadU8, read_vec_u16, encode_vec_u16};
+
+  let items = [(1u8, PayloadU8::from_slice(b"ab")), (2u8, PayloadU8::from_slice(b"cde"))];
+  let mut bytes = vec![0xff];
+  encode_vec_u16(&mut bytes, &items);
+
+  let mut r = Reader::init(&bytes);
+  r.read_u8().unwrap();
+  let spans = read_vec_u16::<Spanned<(u8, PayloadU8)>>(&mut r).unwrap();
+  assert_eq!(spans[0].span, 3 .. 7);
+  assert_eq!(spans[1].span, 7 .. 12);
+  assert_eq!(spans[1].bytes_in(&bytes), &[2, 3, b'c', b'd', b'e']);
+  assert_eq!(spans[1].1, PayloadU8::from_slice(b"cde"));
+
+  //offsets keep counting inside sub readers
+  let mut r = Reader::init(&bytes);
+  r.take(3).unwrap();
+  let mut sub = r.sub(4).unwrap();
+  assert_eq!(sub.used(), 0);
+  assert_eq!(sub.absolute_offset(), 3);
+  let one = Spanned::<u8>::read(&mut sub).unwrap();
+  assert_eq!(one.span, 3 .. 4);
+  assert_eq!(one.get_encoding(), vec![1]);
+  assert_eq!(one.into_inner(), 1);
+}
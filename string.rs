@@ -0,0 +1,276 @@
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[cfg(feature = "bytes")]
+use bytes::BufMut;
+
+use super::codec::{Codec, Reader, extend};
+use super::error::{DecodeError, EncodeError};
+use super::owned::IntoOwned;
+use super::prefix::{LengthPrefix, U8, U16, U24, U32, U64};
+#[cfg(feature = "bytes")]
+use super::prefix::put_prefix;
+
+///What a `Str` may hold beyond being UTF-8. Implemented by the
+///marker types `Utf8`, `Ascii`, `Hostname` and `Printable`.
+pub trait StrRule {
+  ///named by `DecodeError::InvalidValue` when the rule fails
+  const WHAT: &'static str;
+
+  fn check(s: &str) -> bool;
+}
+
+///Any UTF-8 string
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub struct Utf8;
+impl StrRule for Utf8 {
+  const WHAT: &'static str = "utf-8 string";
+  fn check(_: &str) -> bool {
+    true
+  }
+}
+
+///ASCII only
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub struct Ascii;
+impl StrRule for Ascii {
+  const WHAT: &'static str = "ascii string";
+  fn check(s: &str) -> bool {
+    s.is_ascii()
+  }
+}
+
+///A DNS hostname following RFC 1123: dot separated labels of 1 to
+///63 letters, digits and hyphens which do not start or end with a
+///hyphen, at most 253 bytes in all and with no trailing dot
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub struct Hostname;
+impl StrRule for Hostname {
+  const WHAT: &'static str = "hostname";
+  fn check(s: &str) -> bool {
+    !s.is_empty() && s.len() <= 253 && s.split('.').all(|label| {
+      let b = label.as_bytes();
+      !b.is_empty() && b.len() <= 63 &&
+        b[0] != b'-' && b[b.len() - 1] != b'-' &&
+        b.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'-')
+    })
+  }
+}
+
+///The ASN.1 PrintableString set: letters, digits, space and
+///`'()+,-./:=?`
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub struct Printable;
+impl StrRule for Printable {
+  const WHAT: &'static str = "printable string";
+  fn check(s: &str) -> bool {
+    s.bytes().all(|c| c.is_ascii_alphanumeric() || b" '()+,-./:=?".contains(&c))
+  }
+}
+
+///A string with an `L` length prefix, checked against the rule `C`
+///once when built or decoded and usable as a `&str` from then on.
+///Input which breaks the rule fails to decode with `InvalidValue`.
+pub struct Str<'a, L, C = Utf8>(Cow<'a, str>, PhantomData<fn() -> (L, C)>);
+
+///UTF-8 string with a u8 length prefix
+pub type StrU8<'a> = Str<'a, U8>;
+///UTF-8 string with a big endian u16 length prefix
+pub type StrU16<'a> = Str<'a, U16>;
+///UTF-8 string with a big endian u24 length prefix
+pub type StrU24<'a> = Str<'a, U24>;
+///UTF-8 string with a big endian u32 length prefix
+pub type StrU32<'a> = Str<'a, U32>;
+///UTF-8 string with a big endian u64 length prefix
+pub type StrU64<'a> = Str<'a, U64>;
+///ASCII string with a u8 length prefix
+pub type AsciiStrU8<'a> = Str<'a, U8, Ascii>;
+///ASCII string with a big endian u16 length prefix
+pub type AsciiStrU16<'a> = Str<'a, U16, Ascii>;
+///ASCII string with a big endian u24 length prefix
+pub type AsciiStrU24<'a> = Str<'a, U24, Ascii>;
+///ASCII string with a big endian u32 length prefix
+pub type AsciiStrU32<'a> = Str<'a, U32, Ascii>;
+///ASCII string with a big endian u64 length prefix
+pub type AsciiStrU64<'a> = Str<'a, U64, Ascii>;
+///Hostname with a big endian u16 length prefix, as in TLS SNI
+pub type HostnameU16<'a> = Str<'a, U16, Hostname>;
+///PrintableString, with a big endian u16 length prefix unless
+///another is given
+pub type PrintableString<'a, L = U16> = Str<'a, L, Printable>;
+
+impl<'a, L, C: StrRule> Str<'a, L, C> {
+
+  ///Borrow `s`, or None if it breaks the rule
+  pub fn new(s: &'a str) -> Option<Self> {
+    Str::checked(Cow::Borrowed(s))
+  }
+
+  ///Take ownership of `s`, or None if it breaks the rule
+  pub fn from_string(s: String) -> Option<Self> {
+    Str::checked(Cow::Owned(s))
+  }
+
+  fn checked(s: Cow<'a, str>) -> Option<Self> {
+    if C::check(&s) {
+      Some(Str(s, PhantomData))
+    } else {
+      None
+    }
+  }
+}
+
+impl<'a, L, C> Str<'a, L, C> {
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  pub fn into_string(self) -> String {
+    self.0.into_owned()
+  }
+}
+
+impl<'a, L, C> Deref for Str<'a, L, C> {
+  type Target = str;
+  fn deref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl<'a, L, C> AsRef<str> for Str<'a, L, C> {
+  fn as_ref(&self) -> &str {
+    &self.0
+  }
+}
+
+impl<'a, L, C> From<Str<'a, L, C>> for String {
+  fn from(x: Str<'a, L, C>) -> Self {
+    x.into_string()
+  }
+}
+
+impl<'a, L, C> Clone for Str<'a, L, C> {
+  fn clone(&self) -> Self {
+    Str(self.0.clone(), PhantomData)
+  }
+}
+
+impl<'a, L, C> PartialEq for Str<'a, L, C> {
+  fn eq(&self, other: &Self) -> bool {
+    self.0 == other.0
+  }
+}
+impl<'a, L, C> Eq for Str<'a, L, C> { }
+
+impl<'a, L, C> PartialEq<str> for Str<'a, L, C> {
+  fn eq(&self, other: &str) -> bool {
+    self.0 == other
+  }
+}
+
+impl<'a, 'b, L, C> PartialEq<&'b str> for Str<'a, L, C> {
+  fn eq(&self, other: &&'b str) -> bool {
+    self.0 == *other
+  }
+}
+
+impl<'a, L, C> Hash for Str<'a, L, C> {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.0.hash(state);
+  }
+}
+
+impl<'a, L, C> fmt::Debug for Str<'a, L, C> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&*self.0, f)
+  }
+}
+
+impl<'a, L, C> fmt::Display for Str<'a, L, C> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl<'a, L: LengthPrefix, C: StrRule> Codec<'a> for Str<'a, L, C> {
+  fn encode(&self, bytes: &mut Vec<u8>) {
+    L::encode_len(self.0.len(), bytes);
+    extend(self.0.as_bytes(), bytes);
+  }
+  fn try_encode(&self, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+    L::check_body_len(self.0.len())?;
+    self.encode(bytes);
+    Ok(())
+  }
+  fn exact_len(&self) -> Option<usize> {
+    Some(L::prefix_len(self.0.len()) + self.0.len())
+  }
+  fn try_encoded_len(&self) -> Result<usize,EncodeError> {
+    L::check_body_len(self.0.len())?;
+    Ok(self.encoded_len())
+  }
+  #[cfg(feature = "bytes")]
+  fn encode_buf<B: BufMut>(&self, buf: &mut B) {
+    put_prefix::<L, _>(self.0.len(), buf);
+    buf.put_slice(self.0.as_bytes());
+  }
+  fn read(r: &mut Reader<'a>) -> Result<Self,DecodeError> {
+    let len = L::read_len(r)?;
+    let err = r.invalid(C::WHAT);
+    let data = r.take(len)?;
+    core::str::from_utf8(data).ok()
+      .and_then(Str::new)
+      .ok_or(err)
+  }
+}
+
+impl<'a, L: 'static, C: 'static> IntoOwned for Str<'a, L, C> {
+  type Owned = Str<'static, L, C>;
+  fn into_owned(self) -> Self::Owned {
+    Str(IntoOwned::into_owned(self.0), PhantomData)
+  }
+}
+
+#[test]
+fn test_validated_strings() {
+  use alloc::string::ToString;
+
+  let s = StrU16::read_exact(b"\x00\x05caf\xc3\xa9").unwrap();
+  assert_eq!(s, "café");
+  assert_eq!(s.len(), 5);
+  assert!(s.starts_with("ca"));
+  assert_eq!(s.get_encoding(), b"\x00\x05caf\xc3\xa9".to_vec());
+  assert_eq!(StrU8::read_exact(b"\x02\xc3\x28"),
+    Err(DecodeError::InvalidValue { offset: 1, what: "utf-8 string" }));
+
+  assert_eq!(AsciiStrU8::read_exact(b"\x02ok").unwrap().as_str(), "ok");
+  assert_eq!(AsciiStrU8::read_exact(b"\x02\xc3\xa9"),
+    Err(DecodeError::InvalidValue { offset: 1, what: "ascii string" }));
+  assert!(AsciiStrU8::new("é").is_none());
+
+  let host = HostnameU16::new("www.example-1.com").unwrap();
+  let enc = host.get_encoding();
+  assert_eq!(HostnameU16::read_exact(&enc), Ok(host));
+  for bad in ["", "a..b", "-a.com", "a-.com", "a_b.com", "example.com.", "\u{e9}.com"] {
+    assert!(HostnameU16::new(bad).is_none(), "{:?}", bad);
+  }
+  assert!(HostnameU16::new(&"a".repeat(63)).is_some());
+  assert!(HostnameU16::new(&"a".repeat(64)).is_none());
+  let mut enc = vec![0, 7];
+  enc.extend_from_slice(b"bad_one");
+  assert_eq!(HostnameU16::read_exact(&enc),
+    Err(DecodeError::InvalidValue { offset: 2, what: "hostname" }));
+
+  let p: PrintableString = PrintableString::new("Example Org (UK)").unwrap();
+  assert_eq!(p.to_string(), "Example Org (UK)");
+  assert!(PrintableString::<U8>::read_exact(b"\x03a*b").is_err());
+
+  let owned = StrU8::from_string(String::from("owned")).unwrap();
+  assert_eq!(String::from(owned), "owned");
+}
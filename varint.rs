@@ -0,0 +1,331 @@
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+use super::codec::{ByteOrder, Reader, put_uint};
+use super::error::DecodeError;
+#[cfg(feature = "alloc")]
+use super::error::EncodeError;
+
+///Largest value a QUIC variable-length integer can hold, 2^62-1.
+pub const QUIC_VARINT_MAX: u64 = (1 << 62) - 1;
+
+/*
+ * Zigzag maps signed integers onto unsigned ones so small
+ * magnitudes stay small: 0,-1,1,-2 -> 0,1,2,3
+ *
+ */
+pub fn zigzag_encode(v: i64) -> u64 {
+  ((v << 1) ^ (v >> 63)) as u64
+}
+pub fn zigzag_decode(v: u64) -> i64 {
+  ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+/*
+ * Encoding LEB128
+ *
+ */
+#[cfg(feature = "alloc")]
+pub fn encode_leb128_u64(mut v: u64, bytes: &mut Vec<u8>) {
+  loop {
+    let b = (v & 0x7f) as u8;
+    v >>= 7;
+    if v == 0 {
+      bytes.push(b);
+      return;
+    }
+    bytes.push(b | 0x80);
+  }
+}
+///Number of bytes `encode_leb128_u64` writes for `v`
+pub fn leb128_len(v: u64) -> usize {
+  (64 - (v | 1).leading_zeros() as usize).div_ceil(7)
+}
+///Write `v` as LEB128 into `slot`, which is `leb128_len(v)` long
+pub(crate) fn put_leb128(mut v: u64, slot: &mut [u8]) {
+  let last = slot.len() - 1;
+  for (i, b) in slot.iter_mut().enumerate() {
+    *b = (v & 0x7f) as u8 | if i < last { 0x80 } else { 0 };
+    v >>= 7;
+  }
+}
+#[cfg(feature = "alloc")]
+pub fn encode_sleb128(mut v: i64, bytes: &mut Vec<u8>) {
+  loop {
+    let b = (v & 0x7f) as u8;
+    v >>= 7;
+    //done once the remaining bits are all copies of the sign bit
+    if (v == 0 && b & 0x40 == 0) || (v == -1 && b & 0x40 != 0) {
+      bytes.push(b);
+      return;
+    }
+    bytes.push(b | 0x80);
+  }
+}
+#[cfg(feature = "alloc")]
+pub fn encode_zigzag(v: i64, bytes: &mut Vec<u8>) {
+  encode_leb128_u64(zigzag_encode(v), bytes)
+}
+
+/*
+ * Encoding QUIC varint, RFC 9000 section 16
+ *
+ */
+#[cfg(feature = "alloc")]
+pub fn encode_quic_varint(v: u64, bytes: &mut Vec<u8>) {
+  debug_assert!(v <= QUIC_VARINT_MAX);
+  if v < (1 << 6) {
+    bytes.push(v as u8);
+  } else if v < (1 << 14) {
+    bytes.extend_from_slice(&(v as u16 | 0x4000).to_be_bytes());
+  } else if v < (1 << 30) {
+    bytes.extend_from_slice(&(v as u32 | 0x8000_0000).to_be_bytes());
+  } else {
+    bytes.extend_from_slice(&(v | 0xc000_0000_0000_0000).to_be_bytes());
+  }
+}
+///Number of bytes `encode_quic_varint` writes for `v`
+pub fn quic_varint_len(v: u64) -> usize {
+  if v < (1 << 6) {
+    1
+  } else if v < (1 << 14) {
+    2
+  } else if v < (1 << 30) {
+    4
+  } else {
+    8
+  }
+}
+///Write `v` as a QUIC varint into `slot`, which is
+///`quic_varint_len(v)` long
+pub(crate) fn put_quic_varint(v: u64, slot: &mut [u8]) {
+  debug_assert!(v <= QUIC_VARINT_MAX);
+  //the top two bits give the length as a power of two
+  let tag = (slot.len().trailing_zeros() as u64) << (8 * slot.len() - 2);
+  put_uint(v | tag, slot, ByteOrder::BigEndian);
+}
+#[cfg(feature = "alloc")]
+pub fn try_encode_quic_varint(v: u64, bytes: &mut Vec<u8>) -> Result<(),EncodeError> {
+  if v > QUIC_VARINT_MAX {
+    return Err(EncodeError::ValueOverflow { max: QUIC_VARINT_MAX, actual: v });
+  }
+  encode_quic_varint(v, bytes);
+  Ok(())
+}
+
+impl<'a> Reader<'a> {
+
+  ///decode an unsigned LEB128 integer, rejecting encodings
+  ///with redundant trailing bytes or more than 64 bits
+  pub fn read_leb128_u64(&mut self) -> Result<u64,DecodeError> {
+    let offset = self.absolute_offset();
+    let mut ret = 0u64;
+    let mut shift = 0;
+    loop {
+      let b = self.read_u8()? as u64;
+      if shift == 63 && b > 1 {
+        return Err(DecodeError::InvalidValue { offset, what: "overlong leb128" });
+      }
+      ret |= (b & 0x7f) << shift;
+      if b & 0x80 == 0 {
+        if b == 0 && shift > 0 {
+          return Err(DecodeError::InvalidValue { offset, what: "non-minimal leb128" });
+        }
+        return Ok(ret);
+      }
+      shift += 7;
+    }
+  }
+
+  ///decode a signed LEB128 integer, rejecting encodings
+  ///with redundant trailing bytes or more than 64 bits
+  pub fn read_sleb128(&mut self) -> Result<i64,DecodeError> {
+    let offset = self.absolute_offset();
+    let mut ret = 0i64;
+    let mut shift = 0;
+    let mut prev = 0u8;
+    loop {
+      let b = self.read_u8()? as u8;
+      //the tenth byte holds bit 63, its other bits must copy it
+      if shift == 63 && b != 0x00 && b != 0x7f {
+        return Err(DecodeError::InvalidValue { offset, what: "overlong sleb128" });
+      }
+      ret |= ((b & 0x7f) as i64) << shift;
+      shift += 7;
+      if b & 0x80 == 0 {
+        //a final byte which only repeats the previous sign bit is redundant
+        if shift > 7 && ((b == 0x00 && prev & 0x40 == 0) || (b == 0x7f && prev & 0x40 != 0)) {
+          return Err(DecodeError::InvalidValue { offset, what: "non-minimal sleb128" });
+        }
+        if shift < 64 && b & 0x40 != 0 {
+          ret |= -1i64 << shift;
+        }
+        return Ok(ret);
+      }
+      prev = b;
+    }
+  }
+
+  ///decode a zigzag encoded LEB128 integer
+  pub fn read_zigzag(&mut self) -> Result<i64,DecodeError> {
+    self.read_leb128_u64().map(zigzag_decode)
+  }
+
+  ///decode a QUIC variable-length integer, rejecting values
+  ///which would fit in a shorter encoding
+  pub fn read_quic_varint(&mut self) -> Result<u64,DecodeError> {
+    let offset = self.absolute_offset();
+    let first = self.read_u8()? as u64;
+    let width = 1usize << (first >> 6);
+    let mut ret = first & 0x3f;
+    for b in self.take(width - 1)? {
+      ret = (ret << 8) | *b as u64;
+    }
+    let min = match width {
+      1 => 0,
+      2 => 1 << 6,
+      4 => 1 << 14,
+      _ => 1 << 30,
+    };
+    if ret < min {
+      return Err(DecodeError::InvalidValue { offset, what: "non-minimal quic varint" });
+    }
+    Ok(ret)
+  }
+
+  ///decode a LEB128 length, which must fit in a usize
+  pub fn read_leb128_len(&mut self) -> Result<usize,DecodeError> {
+    let offset = self.absolute_offset();
+    let len = self.read_leb128_u64()?;
+    if len > (usize::MAX as u64) {
+      return Err(DecodeError::LengthOverflow { offset, len });
+    }
+    Ok(len as usize)
+  }
+
+  ///decode a QUIC varint length, which must fit in a usize
+  pub fn read_quic_varint_len(&mut self) -> Result<usize,DecodeError> {
+    let offset = self.absolute_offset();
+    let len = self.read_quic_varint()?;
+    if len > (usize::MAX as u64) {
+      return Err(DecodeError::LengthOverflow { offset, len });
+    }
+    Ok(len as usize)
+  }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_leb128() {
+  for &v in &[0u64, 1, 127, 128, 300, 0xffff_ffff, u64::MAX] {
+    let mut bytes = Vec::new();
+    encode_leb128_u64(v, &mut bytes);
+    assert_eq!(leb128_len(v), bytes.len());
+    let mut slot = [0u8; 10];
+    put_leb128(v, &mut slot[.. bytes.len()]);
+    assert_eq!(&slot[.. bytes.len()], &bytes[..]);
+    let mut r = Reader::init(&bytes);
+    assert_eq!(r.read_leb128_u64(), Ok(v));
+    assert!(!r.any_left());
+  }
+  let mut bytes = Vec::new();
+  encode_leb128_u64(624485, &mut bytes);
+  assert_eq!(bytes, vec![0xe5, 0x8e, 0x26]);
+
+  //a trailing zero group is non-minimal
+  let mut r = Reader::init(&[0x80, 0x00]);
+  assert_eq!(r.read_leb128_u64(),
+    Err(DecodeError::InvalidValue { offset: 0, what: "non-minimal leb128" }));
+  //more than 64 bits
+  let long = [0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0x02];
+  assert!(Reader::init(&long).read_leb128_u64().is_err());
+  let long = [0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0x81,0x00];
+  assert!(Reader::init(&long).read_leb128_u64().is_err());
+  //ran out of bytes mid value
+  let mut r = Reader::init(&[1, 0x80]);
+  r.read_u8().unwrap();
+  assert_eq!(r.read_leb128_u64(),
+    Err(DecodeError::Truncated { offset: 2, needed: 1, available: 0 }));
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_sleb128_zigzag() {
+  for &v in &[0i64, 1, -1, 63, -64, 64, -65, 1 << 40, i64::MIN, i64::MAX] {
+    let mut bytes = Vec::new();
+    encode_sleb128(v, &mut bytes);
+    let mut r = Reader::init(&bytes);
+    assert_eq!(r.read_sleb128(), Ok(v));
+    assert!(!r.any_left());
+
+    let mut bytes = Vec::new();
+    encode_zigzag(v, &mut bytes);
+    assert_eq!(Reader::init(&bytes).read_zigzag(), Ok(v));
+  }
+  let mut bytes = Vec::new();
+  encode_sleb128(-123456, &mut bytes);
+  assert_eq!(bytes, vec![0xc0, 0xbb, 0x78]);
+  assert_eq!(zigzag_encode(-1), 1);
+  assert_eq!(zigzag_encode(1), 2);
+  assert_eq!(zigzag_decode(3), -2);
+
+  //0x7f alone is -1, padding it out is redundant
+  assert_eq!(Reader::init(&[0x7f]).read_sleb128(), Ok(-1));
+  assert!(Reader::init(&[0xff, 0x7f]).read_sleb128().is_err());
+  assert!(Reader::init(&[0x80, 0x00]).read_sleb128().is_err());
+  //but 0x40 needs a zero byte to stay positive
+  assert_eq!(Reader::init(&[0xc0, 0x00]).read_sleb128(), Ok(64));
+  let long = [0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0xff,0x01];
+  assert!(Reader::init(&long).read_sleb128().is_err());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_quic_varint() {
+  //examples from RFC 9000 appendix A.1
+  let cases: &[(&[u8], u64)] = &[
+    (&[0xc2,0x19,0x7c,0x5e,0xff,0x14,0xe8,0x8c], 151288809941952652),
+    (&[0x9d,0x7f,0x3e,0x7d], 494878333),
+    (&[0x7b,0xbd], 15293),
+    (&[0x25], 37),
+  ];
+  for &(bytes, v) in cases {
+    assert_eq!(Reader::init(bytes).read_quic_varint(), Ok(v));
+    let mut out = Vec::new();
+    encode_quic_varint(v, &mut out);
+    assert_eq!(&out[..], bytes);
+    assert_eq!(quic_varint_len(v), bytes.len());
+    let mut slot = [0u8; 8];
+    put_quic_varint(v, &mut slot[.. bytes.len()]);
+    assert_eq!(&slot[.. bytes.len()], bytes);
+  }
+  //37 may not be sent in two bytes
+  assert_eq!(Reader::init(&[0x40, 0x25]).read_quic_varint(),
+    Err(DecodeError::InvalidValue { offset: 0, what: "non-minimal quic varint" }));
+  assert!(Reader::init(&[0x80, 0x00]).read_quic_varint().is_err());
+  let mut out = Vec::new();
+  assert_eq!(try_encode_quic_varint(1 << 62, &mut out),
+    Err(EncodeError::ValueOverflow { max: QUIC_VARINT_MAX, actual: 1 << 62 }));
+  assert!(out.is_empty());
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn test_varint_payloads() {
+  use super::codec::{Codec,PayloadVarint,PayloadQuicVarint};
+
+  let p = PayloadVarint::new(vec![1u8; 200]);
+  let enc = p.get_encoding();
+  assert_eq!(&enc[..2], &[0xc8, 0x01]);
+  assert_eq!(enc.len(), 202);
+  assert_eq!(PayloadVarint::read(&mut Reader::init(&enc)).unwrap(), p);
+
+  let p = PayloadQuicVarint::from_slice(b"hq-29");
+  let enc = p.get_encoding();
+  assert_eq!(enc, vec![5, b'h',b'q',b'-',b'2',b'9']);
+  assert_eq!(PayloadQuicVarint::read(&mut Reader::init(&enc)).unwrap(), p);
+
+  //non-minimal prefixes are rejected before the body is read
+  let bad = [0x40u8, 0x01, 0xaa];
+  assert!(PayloadQuicVarint::read(&mut Reader::init(&bad)).is_err());
+}
@@ -0,0 +1,316 @@
+use core::fmt;
+use core::marker::PhantomData;
+
+use super::codec::{Codec, Reader, ReaderSettings};
+use super::error::DecodeError;
+
+///Lazy iterator over the elements of a length prefixed vector,
+///decoding each one as it is reached. Elements borrow from the
+///original buffer. Errors are labelled with the element's index,
+///after one the iterator is finished.
+///
+///An iterator from `Reader::iter_vec_*` borrows the reader it was
+///made from and hands back what is left of the allocation budget
+///when it finishes or is dropped, as `end_sub` does.
+pub struct VecIter<'r, 'a, T> {
+  r: Reader<'a>,
+  parent: Option<&'r mut Reader<'a>>,
+  index: usize,
+  failed: bool,
+  _item: PhantomData<fn() -> T>
+}
+
+impl<'r, 'a, T> VecIter<'r, 'a, T> {
+  fn new(r: Reader<'a>, parent: Option<&'r mut Reader<'a>>) -> VecIter<'r, 'a, T> {
+    VecIter { r, parent, index: 0, failed: false, _item: PhantomData }
+  }
+
+  ///Give the parent its budget back. Stopping early is not an
+  ///error, so unlike `end_sub` this does not check the rest was
+  ///read, strict or not.
+  fn end(&mut self) {
+    if let Some(parent) = self.parent.take() {
+      parent.reclaim_alloc(&self.r);
+    }
+  }
+}
+
+impl<'r, 'a, T: Codec<'a>> Iterator for VecIter<'r, 'a, T> {
+  type Item = Result<T,DecodeError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.failed || !self.r.any_left() {
+      self.end();
+      return None;
+    }
+    let ret = self.r.context_index(self.index, Reader::read_element);
+    self.index += 1;
+    self.failed = ret.is_err();
+    Some(ret)
+  }
+}
+
+impl<'r, 'a, T> Drop for VecIter<'r, 'a, T> {
+  fn drop(&mut self) {
+    self.end();
+  }
+}
+
+impl<'r, 'a, T> fmt::Debug for VecIter<'r, 'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("VecIter")
+      .field("left", &self.r.left())
+      .field("failed", &self.failed)
+      .finish()
+  }
+}
+
+///A length prefixed vector whose elements have all been checked to
+///decode, but are kept as the borrowed bytes. It can be stored and
+///iterated any number of times without allocating. Each pass
+///reads as the original reader did, with the same offsets, limits
+///and settings.
+pub struct VecView<'a, T> {
+  bytes: &'a [u8],
+  settings: ReaderSettings<'a>,
+  count: usize,
+  _item: PhantomData<fn() -> T>
+}
+
+impl<'a, T: Codec<'a>> VecView<'a, T> {
+
+  ///Decode every element of a body of `len` bytes once to
+  ///validate them
+  fn validate(r: &mut Reader<'a>, len: usize) -> Result<VecView<'a, T>,DecodeError> {
+    let mut sub = r.sub(len)?;
+    let bytes = sub.rest();
+    let settings = sub.settings();
+    let mut count = 0;
+    while sub.any_left() {
+      sub.context_index(count, Reader::read_element::<T>)?;
+      count += 1;
+    }
+    r.end_sub(sub)?;
+    Ok(VecView { bytes, settings, count, _item: PhantomData })
+  }
+
+  ///number of elements
+  pub fn len(&self) -> usize {
+    self.count
+  }
+
+  ///true if there are no elements
+  pub fn is_empty(&self) -> bool {
+    self.count == 0
+  }
+
+  ///the encoded elements, without the length prefix
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.bytes
+  }
+
+  ///Decode the elements again, in order. Each pass starts from
+  ///the budget the view was validated with.
+  pub fn iter(&self) -> VecIter<'a, 'a, T> {
+    VecIter::new(Reader::with_settings(self.bytes, self.settings), None)
+  }
+}
+
+impl<'a, T: Codec<'a>> IntoIterator for &VecView<'a, T> {
+  type Item = Result<T,DecodeError>;
+  type IntoIter = VecIter<'a, 'a, T>;
+
+  fn into_iter(self) -> VecIter<'a, 'a, T> {
+    self.iter()
+  }
+}
+
+impl<'a, T> Clone for VecView<'a, T> {
+  fn clone(&self) -> Self {
+    *self
+  }
+}
+impl<'a, T> Copy for VecView<'a, T> { }
+
+impl<'a, T> fmt::Debug for VecView<'a, T> {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.debug_struct("VecView")
+      .field("len", &self.count)
+      .field("bytes", &self.bytes)
+      .finish()
+  }
+}
+
+impl<'a> Reader<'a> {
+
+  ///Iterator over the elements of a body of `len` bytes
+  fn iter_body<T: Codec<'a>>(&mut self, len: usize) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let sub = self.sub(len)?;
+    Ok(VecIter::new(sub, Some(self)))
+  }
+
+  ///Skip a u8 length prefixed vector, returning a lazy iterator
+  ///over its elements
+  pub fn iter_vec_u8<T: Codec<'a>>(&mut self) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let len = self.read_u8()?;
+    self.iter_body(len)
+  }
+
+  ///Skip a u16 length prefixed vector, returning a lazy iterator
+  ///over its elements
+  pub fn iter_vec_u16<T: Codec<'a>>(&mut self) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let len = self.read_u16_be()?;
+    self.iter_body(len)
+  }
+
+  ///Skip a u24 length prefixed vector, returning a lazy iterator
+  ///over its elements
+  pub fn iter_vec_u24<T: Codec<'a>>(&mut self) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let len = self.read_u24_be()?;
+    self.iter_body(len)
+  }
+
+  ///Skip a u32 length prefixed vector, returning a lazy iterator
+  ///over its elements
+  pub fn iter_vec_u32<T: Codec<'a>>(&mut self) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let len = self.read_u32_be()?;
+    self.iter_body(len)
+  }
+
+  ///Skip a u64 length prefixed vector, returning a lazy iterator
+  ///over its elements
+  pub fn iter_vec_u64<T: Codec<'a>>(&mut self) -> Result<VecIter<'_, 'a, T>,DecodeError> {
+    let len = self.read_u64_be()?;
+    self.iter_body(len)
+  }
+
+  ///Read a u8 length prefixed vector as a validated view
+  pub fn view_vec_u8<T: Codec<'a>>(&mut self) -> Result<VecView<'a, T>,DecodeError> {
+    let len = self.read_u8()?;
+    VecView::validate(self, len)
+  }
+
+  ///Read a u16 length prefixed vector as a validated view
+  pub fn view_vec_u16<T: Codec<'a>>(&mut self) -> Result<VecView<'a, T>,DecodeError> {
+    let len = self.read_u16_be()?;
+    VecView::validate(self, len)
+  }
+
+  ///Read a u24 length prefixed vector as a validated view
+  pub fn view_vec_u24<T: Codec<'a>>(&mut self) -> Result<VecView<'a, T>,DecodeError> {
+    let len = self.read_u24_be()?;
+    VecView::validate(self, len)
+  }
+
+  ///Read a u32 length prefixed vector as a validated view
+  pub fn view_vec_u32<T: Codec<'a>>(&mut self) -> Result<VecView<'a, T>,DecodeError> {
+    let len = self.read_u32_be()?;
+    VecView::validate(self, len)
+  }
+
+  ///Read a u64 length prefixed vector as a validated view
+  pub fn view_vec_u64<T: Codec<'a>>(&mut self) -> Result<VecView<'a, T>,DecodeError> {
+    let len = self.read_u64_be()?;
+    VecView::validate(self, len)
+  }
+}
+
+#[test]
+fn test_vec_iter_and_view() {
+  use alloc::string::ToString;
+  use alloc::vec::Vec;
+  use super::codec::{PayloadU8, encode_vec_u16};
+
+  let exts = [
+    (0x0000u16, PayloadU8::from_slice(b"example.com")),
+    (0x0010u16, PayloadU8::from_slice(b"h2")),
+    (0x002bu16, PayloadU8::from_slice(&[3, 4])),
+  ];
+  let mut bytes = Vec::new();
+  encode_vec_u16(&mut bytes, &exts);
+  bytes.push(0xee);
+
+  //scan for one entry, the rest is never decoded
+  let mut r = Reader::init(&bytes);
+  let alpn = r.iter_vec_u16::<(u16, PayloadU8)>().unwrap()
+    .filter_map(|x| x.ok())
+    .find(|x| x.0 == 0x0010)
+    .map(|x| x.1);
+  assert_eq!(alpn, Some(PayloadU8::from_slice(b"h2")));
+  assert_eq!(r.read_u8(), Ok(0xee));
+
+  let mut r = Reader::init(&bytes);
+  let view = r.view_vec_u16::<(u16, PayloadU8)>().unwrap();
+  assert_eq!(view.len(), 3);
+  assert_eq!(view.as_bytes(), &bytes[2 .. bytes.len() - 1]);
+  for _ in 0..2 {
+    let items: Result<Vec<_>,_> = view.iter().collect();
+    assert_eq!(items.unwrap(), exts.to_vec());
+  }
+  let stored = view;
+  assert_eq!((&stored).into_iter().count(), 3);
+
+  //the iterator stops after the first bad element, the view
+  //refuses to be built at all
+  let bad = [0u8, 5, 0,1, 9, 0xaa, 0xbb];
+  let mut r = Reader::init(&bad);
+  let mut it = r.iter_vec_u16::<(u16, PayloadU8)>().unwrap();
+  assert!(it.next().unwrap().is_err());
+  assert!(it.next().is_none());
+  assert!(Reader::init(&bad).view_vec_u16::<(u16, PayloadU8)>().is_err());
+
+  //the outer prefix is checked up front
+  assert!(Reader::init(&[0, 9, 1]).iter_vec_u16::<u8>().is_err());
+
+  //errors name the element which failed
+  let mut broken = bytes.clone();
+  broken[18] = 9;
+  let err = Reader::init(&broken).iter_vec_u16::<(u16, PayloadU8)>().unwrap()
+    .find_map(|x| x.err()).unwrap();
+  assert_eq!(err.path().unwrap().to_string(), "[1]");
+  let err = Reader::init(&broken).view_vec_u16::<(u16, PayloadU8)>().unwrap_err();
+  assert_eq!(err.path().unwrap().to_string(), "[1]");
+
+  //every pass over a view reads at the original offsets
+  use super::span::Spanned;
+  let mut r = Reader::init(&bytes);
+  let view = r.view_vec_u16::<Spanned<(u16, PayloadU8)>>().unwrap();
+  for _ in 0..2 {
+    let spans: Vec<_> = view.iter().map(|x| x.unwrap().span).collect();
+    assert_eq!(spans, vec![2 .. 16, 16 .. 21, 21 .. 26]);
+  }
+
+  //allocations made by the elements come out of the budget of
+  //the reader the vector was read from
+  use super::codec::DecodeLimits;
+  use super::counted::CountedVecU8;
+  let lists = [CountedVecU8::new(vec![1u8]), CountedVecU8::new(vec![2u8])];
+  let mut bytes = Vec::new();
+  encode_vec_u16(&mut bytes, &lists);
+  bytes.extend_from_slice(&[1, 3]);
+  let limits = DecodeLimits { max_alloc: 8, ..DecodeLimits::UNLIMITED };
+  let spent = Err(DecodeError::LimitExceeded { offset: 8, limit: "max_alloc" });
+  let mut r = Reader::init(&bytes);
+  r.set_limits(limits);
+  assert_eq!(r.iter_vec_u16::<CountedVecU8<u8>>().unwrap().count(), 2);
+  assert_eq!(CountedVecU8::<u8>::read(&mut r), spent);
+  let mut r = Reader::init(&bytes);
+  r.set_limits(limits);
+  assert_eq!(r.view_vec_u16::<CountedVecU8<u8>>().unwrap().len(), 2);
+  assert_eq!(CountedVecU8::<u8>::read(&mut r), spent);
+  //an iterator dropped part way hands back what it did not use
+  let mut r = Reader::init(&bytes);
+  r.set_limits(limits);
+  assert!(r.iter_vec_u16::<CountedVecU8<u8>>().unwrap().next().unwrap().is_ok());
+  assert_eq!(CountedVecU8::<u8>::read(&mut r), Ok(CountedVecU8::new(vec![3])));
+
+  //and payloads from a shared buffer stay shared
+  #[cfg(feature = "bytes")]
+  {
+    use super::shared::SharedPayloadU8;
+    let wire = ::bytes::Bytes::from(vec![0, 3, 2, b'h', b'i']);
+    let view = Reader::shared(&wire).view_vec_u16::<SharedPayloadU8>().unwrap();
+    let p = view.iter().next().unwrap().unwrap();
+    assert_eq!(p.0.as_ptr(), wire[3 ..].as_ptr());
+  }
+}